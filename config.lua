@@ -14,43 +14,50 @@ function is_orthogonal(a, b)
 	end
 end
 
-last_run_angle = 0
+-- player and players are set by the game: in netplay input is called
+-- for both players with player set to the player of the input
+last_run_angle = {}
 run_dir_buf = {}
+for i = 0, players-1 do
+	last_run_angle[i] = 0
+	run_dir_buf[i] = {}
+end
 
 function input(state, scancode, code)
 	if state == "pressed" then
 		if run_keys[code] then run_key_pressed(run_keys[code])
-		-- elseif code == shoot_key then set_player_shoot(true)
-		-- elseif code == "r" then set_player_weapon("sniper", 0.4, 1, 1)
+		elseif code == shoot_key then set_player_shoot(true)
+		elseif code == "r" then set_player_weapon("sniper", 0.4, 1, 1)
 		end
 	else
 		if run_keys[code] then run_key_released(run_keys[code])
-		-- elseif code == shoot_key then set_player_shoot(false)
+		elseif code == shoot_key then set_player_shoot(false)
 		end
 	end
-	local a = run_dir_buf[1] or ""
 end
 
 function run_key_pressed(run_dir)
+	local buf = run_dir_buf[player]
 	local index = 1
-	for i, v in ipairs(run_dir_buf) do
+	for i, v in ipairs(buf) do
 		if run_dir == v then
 			return
 		end
 		index = index + 1
 	end
-	run_dir_buf[index] = run_dir
+	buf[index] = run_dir
 	update_player_run_dir()
 end
 
 function run_key_released(run_dir)
+	local buf = run_dir_buf[player]
 	local remove = false
-	for i, value in ipairs(run_dir_buf) do
+	for i, value in ipairs(buf) do
 		if value == run_dir then
 			remove = true
 		end
 		if remove then
-			run_dir_buf[i] = run_dir_buf[i+1]
+			buf[i] = buf[i+1]
 		end
 	end
 	if remove then
@@ -66,7 +73,7 @@ PI2 = PI/2
 function update_player_run_dir()
 	local vertical = nil;
 	local horizontal = nil;
-	for _, value in ipairs(run_dir_buf) do
+	for _, value in ipairs(run_dir_buf[player]) do
 		if value == "right" or value == "left" then
 			horizontal = value
 		else
@@ -74,7 +81,7 @@ function update_player_run_dir()
 		end
 	end
 
-	local angle = last_run_angle
+	local angle = last_run_angle[player]
 	local strength = 1.0
 
 	if vertical == "down" then
@@ -102,13 +109,13 @@ function update_player_run_dir()
 			strength = 0
 		end
 	end
-	last_run_angle = angle
+	last_run_angle[player] = angle
 
 	set_player_force(angle, strength)
 end
 
 function mouse_moved(x, y)
-	-- set_player_aim(math.atan2(y,x))
+	set_player_aim(math.atan2(y,x))
 end
 
 function mouse_wheel(horizontal, vertical)
@@ -122,6 +129,11 @@ end
 
 set_zoom(zoom)
 add_wall(0, 0, 5, 10)
-add_character(10, 10, velocity, time_to_reach_vmax, weight)
--- set_player_weapon("sniper", 0.4, 1, 1)
+for i = 0, players-1 do
+	add_character(10 + 2*i, 10, velocity, time_to_reach_vmax, weight)
+end
+-- in netplay each peer only arms its own player: use the r key instead
+if players == 1 then
+	set_player_weapon("sniper", 0.4, 1, 1)
+end
 fill_physic_world()
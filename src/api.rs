@@ -27,6 +27,7 @@ macro_rules! api_caller {
     ($( $(#[doc = $doc:expr])* fn $func:ident ($($arg:ident: $typ:ty),*);)* + entities) => {
         #[allow(non_camel_case_types)]
         #[doc(hidden)]
+        #[derive(Clone)]
         pub enum CallerMsg {
             EntityBuilder(::entities::EntityBuilderMsg),
             $($func((), $($typ),*)),*
@@ -70,18 +71,61 @@ macro_rules! api_caller {
 // fn pause(&mut self);
 // fn resume(&mut self);
 api_caller! {
-    // /// Change weapon of player
-    // ///
-    // /// kind: ["sniper" | "shotgun" | "hammer" | "uzis"],
-    // fn set_player_weapon(kind: String, reload: f32, setup: f32, setdown: f32);
-    // /// Set player shoot
-    // fn set_player_shoot(shoot: bool);
+    /// Change weapon of the active player
+    ///
+    /// kind: ["sniper" | "shotgun" | "hammer" | "uzis"],
+    /// reload, setup and setdown are durations in seconds
+    fn set_player_weapon(kind: String, reload: f32, setup: f32, setdown: f32);
+    /// Set the active player shoot
+    fn set_player_shoot(shoot: bool);
     /// Set zoom
     fn set_zoom(zoom: f32);
-    // /// Set player orienation (gun ...)
-    // fn set_player_aim(angle: f32);
-    /// Set player force
+    /// Set the active player orientation (gun ...)
+    fn set_player_aim(angle: f32);
+    /// Set the armor of the active player
+    ///
+    /// bullet, explosion and melee are the parts of damages absorbed in [0,1]
+    /// until points are spent
+    fn set_player_armor(points: f32, bullet: f32, explosion: f32, melee: f32);
+    /// Set the rays cast when a kind of weapon fires
+    ///
+    /// * damage: of each ray
+    /// * spread: maximal angle between a ray and the aim
+    /// * penetration: number of bodies with health a ray goes through
+    fn set_hitscan(kind: String, damage: f32, range: f32, rays: u32, spread: f32, penetration: u32);
+    /// Explode at the next update, see explosions.rs
+    ///
+    /// * damage: at the center of the blast
+    /// * impulse: change of momentum at the center of the blast
+    fn explode(x: f32, y: f32, radius: f32, damage: f32, impulse: f32);
+    /// Set the blast of projectiles with explode impact
+    fn set_projectile_explosion(radius: f32, impulse: f32);
+    /// Open, close or lock the door at the next update
+    fn set_door_state(door: u32, state: String);
+    /// Give the key of locked doors to the active player
+    fn give_player_key(key: String);
+    /// Attach a force field to the entity, replacing its previous one
+    ///
+    /// * strength: attraction if positive, repulsion if negative
+    /// * falloff: constant, linear or inverse_square
+    /// * group, mask: dynamic bodies are affected if their group is in mask
+    ///   and group is in their mask
+    fn set_field(entity: u32, strength: f32, radius: f32, falloff: String, group: u32, mask: u32);
+    /// Remove the force field of the entity
+    fn remove_field(entity: u32);
+    /// Set the part of the normal velocity kept and of the tangential velocity
+    /// lost when the entity bounces, see add_projectile
+    fn set_bounce(entity: u32, restitution: f32, friction: f32);
+    /// Set the move force of the active player
     fn set_player_force(angle: f32, strength: f32);
+    /// Change the momentum of the entity at the next physic update
+    fn apply_impulse(entity: u32, angle: f32, strength: f32);
+    /// Give the contacts of the entity to collision, disabled by default
+    fn set_collision_callback(entity: u32, enabled: bool);
+    /// Set the torque rotating the entity, counterclockwise if strength is positive
+    fn set_torque(entity: u32, strength: f32);
+    /// Set the damping slowing down the rotation of the entity
+    fn set_angular_damping(entity: u32, damping: f32);
     /// Quit the game
     fn quit();
     /// Show notification on the screen
@@ -90,6 +134,38 @@ api_caller! {
     fn print(msg: String);
     /// Fill physic world with static and dynamic physic elements
     fn fill_physic_world();
+    /// Build the level of the file and fill the physic world,
+    /// spawn_point is called for each spawn point of the level
+    fn load_level(path: String);
+    /// Generate a maze of width x height cells with its lower left corner at the origin,
+    /// the same seed gives the same maze. Fill the physic world,
+    /// spawn_point is called for the center of each room (or each dead end if no room).
+    /// corridor_width and wall_thickness must be positive
+    fn generate_maze(seed: u32, width: u32, height: u32, corridor_width: f32, wall_thickness: f32, rooms: u32);
+    /// Find a path avoiding walls for an agent of radius,
+    /// the result is given to path_found with the same request id
+    fn find_path(request: u32, x0: f32, y0: f32, x1: f32, y1: f32, radius: f32);
+    /// Save the world to the file
+    fn save_world(path: String);
+    /// Replace the world by the one saved in the file
+    fn load_world(path: String);
+    /// Round physic state to multiples of quantum after each update,
+    /// needed for deterministic simulation across platforms (netplay):
+    /// without it only peers on the same platform stay in sync.
+    /// 0 disable it
+    fn set_physic_quantum(quantum: f32);
+    /// Enable or disable the AI director, disabled by default
+    fn set_director_enabled(enabled: bool);
+    /// If false the director only calls director_spawn and builds nothing
+    fn set_director_auto_spawn(auto_spawn: bool);
+    /// Set a tuning parameter of the director by name, see director.rs
+    fn set_director_parameter(name: String, value: f32);
+    /// Override the stress of the players, in [0,1]
+    fn set_director_stress(stress: f32);
+    /// Override the phase of the director: build_up, peak or relax
+    fn set_director_phase(phase: String);
+    /// Give the state of the director to director_state
+    fn inspect_director();
     + entities
 }
 
@@ -150,4 +226,60 @@ api_callee! {
 
 /// Function called at each update
     fn update(dt: f32);
+
+/// Two entities have started or ended to collide,
+/// one of them at least is enabled with set_collision_callback.
+/// Contacts with sensors are given to sensor_enter and sensor_exit.
+///
+/// * a, b: the ids of the entities
+/// * state: started or ended
+    fn collision(a: u32, b: u32, state: String);
+
+/// An entity has entered the sensor (a trigger zone for example).
+    fn sensor_enter(sensor: u32, entity: u32);
+
+/// An entity has exited the sensor.
+    fn sensor_exit(sensor: u32, entity: u32);
+
+/// Result of find_path: path is a table of points {x=..., y=...} from start to end,
+/// nil if there is no path.
+    fn path_found(request: u32, path: Vec<[f32; 2]>);
+
+/// The weapon of the entity has fired.
+///
+/// * kind: sniper, shotgun, hammer or uzis
+    fn weapon_fired(entity: u32, kind: String);
+
+/// The projectile has hit the entity at (x, y), see add_projectile.
+    fn on_impact(projectile: u32, entity: u32, x: f32, y: f32);
+
+/// The hero has taken the weapon of the pickup, see add_pickup.
+    fn pickup_taken(pickup: u32, hero: u32, kind: String);
+
+/// The entity has no health left and has been removed from the world.
+///
+/// * cause: kind of the last damage: bullet, explosion or melee
+/// * source: entity that caused the last damage (the shooter, the owner of
+///   the projectile ...), -1 if unknown
+    fn on_death(entity: u32, cause: String, source: i32);
+
+/// The door has changed of state: open, closed or locked.
+    fn door_changed(door: u32, state: String);
+
+/// A spawn point of the level loaded with load_level or generate_maze.
+    fn spawn_point(x: f32, y: f32);
+
+/// The director has decided to spawn something out of sight of the heroes.
+///
+/// * kind: zombie or pickup
+    fn director_spawn(kind: String, x: f32, y: f32);
+
+/// The director has entered a new phase: build_up, peak or relax.
+    fn director_phase(phase: String, stress: f32);
+
+/// Result of inspect_director.
+///
+/// * calm: time since the last fight
+/// * parameters: table of the tuning parameters by name
+    fn director_state(enabled: bool, stress: f32, phase: String, calm: f32, parameters: Vec<(String, f32)>);
 }
@@ -3,11 +3,19 @@ use specs;
 use glium;
 use glium::backend::Facade;
 use graphics::{Graphics, Frame, Camera};
+use physics::{self, ContactState};
 use specs::Join;
 use update_systems;
 use draw_systems;
 use components;
 use resources;
+use snapshot::Snapshot;
+use save;
+use level;
+use maze;
+use entities::{self, EntityBuilder};
+use weapons;
+use doors::DoorState;
 use utils::UpdateContext;
 use num_cpus;
 
@@ -19,12 +27,24 @@ const NOTIFICATION_MAX: usize = 10;
 
 pub struct App {
     must_quit: bool,
-    graphics: Graphics,
+    ticks: u64,
+    /// player controlled by the player api calls
+    active_player: u32,
+    /// player followed by the camera
+    local_player: u32,
+    graphics: Option<Graphics>,
     planner: specs::Planner<UpdateContext>,
 }
 
 impl App {
     pub fn new<F: Facade>(facade: &F) -> Self {
+        App::with_graphics(Some(Graphics::new(facade).unwrap()))
+    }
+    /// App without graphics: draw must not be called
+    pub fn new_headless() -> Self {
+        App::with_graphics(None)
+    }
+    fn with_graphics(graphics: Option<Graphics>) -> Self {
         let mut world = specs::World::new();
 
         resources::add_resources(&mut world);
@@ -34,8 +54,11 @@ impl App {
         update_systems::add_systems(&mut planner);
 
         App {
-            graphics: Graphics::new(facade).unwrap(),
+            graphics: graphics,
             must_quit: false,
+            ticks: 0,
+            active_player: 0,
+            local_player: 0,
             planner: planner,
         }
     }
@@ -43,6 +66,56 @@ impl App {
         let context = UpdateContext { dt: dt };
         self.planner.dispatch(context);
         self.planner.wait();
+        self.ticks += 1;
+
+        let spawns = ::std::mem::replace(&mut self.planner.mut_world().write_resource::<resources::EntitySpawns>().0, Vec::new());
+        for msg in spawns {
+            self.build_entity(msg);
+        }
+
+        let world = self.planner.mut_world();
+        let collision_events = world.read_resource::<resources::CollisionEvents>();
+        let types = world.read::<components::PhysicType>();
+        let collision_callbacks = world.read::<components::CollisionCallback>();
+        let ref mut callbacks = world.write_resource::<resources::LuaCallbacks>().0;
+        for event in &collision_events.events {
+            if event.sensor {
+                let a_is_sensor = types.get(event.a).map(|typ| typ.sensor).unwrap_or(false);
+                let (sensor, other) = if a_is_sensor { (event.a, event.b) } else { (event.b, event.a) };
+                let function = match event.state {
+                    ContactState::Started => "sensor_enter",
+                    ContactState::Ended => "sensor_exit",
+                    ContactState::Persisting => continue,
+                };
+                callbacks.push(format!("{}({},{})", function, sensor.get_id(), other.get_id()));
+            } else if collision_callbacks.get(event.a).is_some() || collision_callbacks.get(event.b).is_some() {
+                let state = match event.state {
+                    ContactState::Started => "started",
+                    ContactState::Ended => "ended",
+                    ContactState::Persisting => continue,
+                };
+                callbacks.push(format!("collision({},{},\"{}\")", event.a.get_id(), event.b.get_id(), state));
+            }
+        }
+    }
+    pub fn set_active_player(&mut self, player: u32) {
+        self.active_player = player;
+    }
+    pub fn set_local_player(&mut self, player: u32) {
+        self.local_player = player;
+    }
+    pub fn snapshot(&mut self) -> Snapshot {
+        Snapshot::new(self.planner.mut_world(), self.ticks)
+    }
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        snapshot.restore(self.planner.mut_world());
+        self.ticks = snapshot.ticks;
+    }
+    /// Lua commands to execute since last call
+    pub fn lua_callbacks(&mut self) -> Vec<String> {
+        let world = self.planner.mut_world();
+        let ref mut callbacks = world.write_resource::<resources::LuaCallbacks>().0;
+        ::std::mem::replace(callbacks, Vec::new())
     }
     pub fn draw(&mut self, frame: glium::Frame) {
         let camera = {
@@ -52,12 +125,15 @@ impl App {
             let zoom = world.read_resource::<resources::Zoom>().0;
 
             let mut pos = [0.,0.];
-            for (_, state) in (&players, &states).iter() {
-                pos =  state.pos;
+            for (player, state) in (&players, &states).iter() {
+                if player.0 == self.local_player {
+                    pos = state.pos;
+                }
             }
             Camera::new(pos[0], pos[1], zoom)
         };
-        let mut frame = Frame::new(&mut self.graphics, frame, &camera);
+        let graphics = self.graphics.as_mut().expect("headless app can't draw");
+        let mut frame = Frame::new(graphics, frame, &camera);
         draw_systems::run(self.planner.mut_world(), &mut frame);
         frame.finish().unwrap();
     }
@@ -65,7 +141,35 @@ impl App {
         self.must_quit
     }
     pub fn resized(&mut self, _width: u32, _height: u32) {
-        self.graphics.resize().unwrap();
+        if let Some(ref mut graphics) = self.graphics {
+            graphics.resize().unwrap();
+        }
+    }
+    /// Human readable state of the world
+    pub fn summary(&mut self) -> String {
+        let ticks = self.ticks;
+        let world = self.planner.mut_world();
+        let dynamics = world.read::<components::PhysicDynamic>();
+        let statics = world.read::<components::PhysicStatic>();
+        let players = world.read::<components::PlayerControl>();
+        let states = world.read::<components::PhysicState>();
+
+        let number_of_dynamics = (&dynamics, &states).iter().count();
+        let number_of_statics = (&statics, &states).iter().count();
+
+        let level = world.read_resource::<resources::Level>();
+
+        let mut summary = format!("level: {}\nticks: {}\ndynamic entities: {}\nstatic entities: {}\nchecksum: {:016x}",
+                                  level.name, ticks, number_of_dynamics, number_of_statics, physics::checksum(world));
+        for (player, state) in (&players, &states).iter() {
+            summary.push_str(&*format!("\nplayer {}: pos [{}, {}] vel [{}, {}]",
+                                       player.0, state.pos[0], state.pos[1], state.vel[0], state.vel[1]));
+        }
+        let director = world.read_resource::<resources::Director>();
+        if director.enabled {
+            summary.push_str(&*format!("\ndirector: {} stress {} calm {}", director.phase.name(), director.stress, director.calm));
+        }
+        summary
     }
     pub fn set_cursor(&mut self, x: f32, y: f32) {
         let mut cursor = self.planner.mut_world().write_resource::<resources::Cursor>();
@@ -78,24 +182,86 @@ impl App {
     // }
 }
 
+/// Store the spawn points and call lua spawn_point for each
+fn add_spawn_points(world: &mut specs::World, spawn_points: Vec<[f32; 2]>) {
+    let ref mut callbacks = world.write_resource::<resources::LuaCallbacks>().0;
+    for point in &spawn_points {
+        callbacks.push(format!("spawn_point({},{})", point[0], point[1]));
+    }
+    world.write_resource::<resources::SpawnPoints>().0.extend(spawn_points);
+}
+
 impl_entity_builder!(App);
 
 impl api::Caller for App {
-    // fn set_player_aim(&mut self, angle: f32) {
-    //     let world = self.planner.mut_world();
-    //     let players = world.read::<components::PlayerControl>();
-    //     let mut aims = world.write::<components::Aim>();
-    //     for (_, mut aim) in (&players, &mut aims).iter() {
-    //         aim.0 = angle;
-    //     }
-    // }
+    fn set_player_aim(&mut self, angle: f32) {
+        let world = self.planner.mut_world();
+        let players = world.read::<components::PlayerControl>();
+        let mut aims = world.write::<components::Aim>();
+        for (player, aim) in (&players, &mut aims).iter() {
+            if player.0 == self.active_player {
+                aim.0 = angle;
+            }
+        }
+    }
     fn set_player_force(&mut self, angle: f32, strength: f32) {
         let world = self.planner.mut_world();
         let players = world.read::<components::PlayerControl>();
-        let mut forces = world.write::<components::PhysicForce>();
-        for (_, force) in (&players, &mut forces).iter() {
-            force.angle = angle;
-            force.strength = strength;
+        let mut forces = world.write::<components::PhysicForces>();
+        for (player, forces) in (&players, &mut forces).iter() {
+            if player.0 == self.active_player {
+                let force = forces.named(entities::MOVE_FORCE);
+                force.angle = angle;
+                force.strength = strength;
+            }
+        }
+    }
+    fn set_bounce(&mut self, entity: u32, restitution: f32, friction: f32) {
+        let world = self.planner.mut_world();
+        if let Some(entity) = entities::entity_from_id(world, "set_bounce", entity) {
+            match world.write::<components::PhysicType>().get_mut(entity) {
+                Some(typ) => {
+                    typ.restitution = restitution;
+                    typ.friction = friction;
+                }
+                None => println!("set_bounce: entity {} has no physic type", entity.get_id()),
+            }
+        }
+    }
+    fn apply_impulse(&mut self, entity: u32, angle: f32, strength: f32) {
+        let world = self.planner.mut_world();
+        if let Some(entity) = entities::entity_from_id(world, "apply_impulse", entity) {
+            match world.write::<components::PhysicForces>().get_mut(entity) {
+                Some(forces) => forces.add_impulse([strength * angle.cos(), strength * angle.sin()]),
+                None => println!("apply_impulse: entity {} has no forces", entity.get_id()),
+            }
+        }
+    }
+    fn set_collision_callback(&mut self, entity: u32, enabled: bool) {
+        let world = self.planner.mut_world();
+        if let Some(entity) = entities::entity_from_id(world, "set_collision_callback", entity) {
+            let mut collision_callbacks = world.write::<components::CollisionCallback>();
+            // a null storage can't be written over
+            if enabled && collision_callbacks.get(entity).is_none() {
+                collision_callbacks.insert(entity, components::CollisionCallback);
+            } else if !enabled {
+                collision_callbacks.remove(entity);
+            }
+        }
+    }
+    fn set_torque(&mut self, entity: u32, strength: f32) {
+        let world = self.planner.mut_world();
+        if let Some(entity) = entities::entity_from_id(world, "set_torque", entity) {
+            world.write::<components::PhysicTorque>().insert(entity, components::PhysicTorque {
+                strength: strength,
+                coef: 1.,
+            });
+        }
+    }
+    fn set_angular_damping(&mut self, entity: u32, damping: f32) {
+        let world = self.planner.mut_world();
+        if let Some(entity) = entities::entity_from_id(world, "set_angular_damping", entity) {
+            world.write::<components::PhysicAngularDamping>().insert(entity, components::PhysicAngularDamping(damping));
         }
     }
     fn quit(&mut self) {
@@ -118,41 +284,235 @@ impl api::Caller for App {
         let mut physic_world = world.write_resource::<resources::PhysicWorld>();
         physic_world.fill(world);
     }
+    fn load_level(&mut self, path: String) {
+        let world = self.planner.mut_world();
+        match level::load_level(world, &*path) {
+            Ok(spawn_points) => {
+                add_spawn_points(world, spawn_points);
+                world.write_resource::<resources::PhysicWorld>().fill(world);
+            }
+            Err(e) => println!("load_level: {}", e),
+        }
+    }
+    fn generate_maze(&mut self, seed: u32, width: u32, height: u32, corridor_width: f32, wall_thickness: f32, rooms: u32) {
+        let world = self.planner.mut_world();
+        let maze = match maze::generate(seed, width, height, corridor_width, wall_thickness, rooms) {
+            Ok(maze) => maze,
+            Err(e) => {
+                println!("generate_maze: {}", e);
+                return;
+            }
+        };
+        for wall in maze.walls {
+            entities::add_wall(world, wall[0], wall[1], wall[2], wall[3]);
+        }
+        add_spawn_points(world, maze.spawn_points);
+        world.write_resource::<resources::PhysicWorld>().fill(world);
+    }
+    fn find_path(&mut self, request: u32, x0: f32, y0: f32, x1: f32, y1: f32, radius: f32) {
+        let world = self.planner.mut_world();
+        let physic_world = world.read_resource::<resources::PhysicWorld>();
+        let mut navigation = world.write_resource::<resources::Navigation>();
+        let path = navigation.find_path(&physic_world, [x0, y0], [x1, y1], radius, entities::WALL_GROUP);
+
+        let path = match path {
+            Some(path) => {
+                let points = path.iter()
+                    .map(|p| format!("{{x={},y={}}}", p[0], p[1]))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", points.join(","))
+            }
+            None => "nil".into(),
+        };
+        let ref mut callbacks = world.write_resource::<resources::LuaCallbacks>().0;
+        callbacks.push(format!("path_found({},{})", request, path));
+    }
+    fn save_world(&mut self, path: String) {
+        if let Err(e) = save::save_world(self.planner.mut_world(), self.ticks, &*path) {
+            println!("save_world: {}", e);
+        }
+    }
+    fn load_world(&mut self, path: String) {
+        match save::load_world(self.planner.mut_world(), &*path) {
+            Ok(ticks) => self.ticks = ticks,
+            Err(e) => println!("load_world: {}", e),
+        }
+    }
+    fn set_physic_quantum(&mut self, quantum: f32) {
+        let world = self.planner.mut_world();
+        let mut stepping = world.write_resource::<resources::PhysicStepping>();
+        stepping.quantum = if quantum > 0. { Some(quantum) } else { None };
+    }
+    fn set_director_enabled(&mut self, enabled: bool) {
+        self.planner.mut_world().write_resource::<resources::Director>().enabled = enabled;
+    }
+    fn set_director_auto_spawn(&mut self, auto_spawn: bool) {
+        self.planner.mut_world().write_resource::<resources::Director>().auto_spawn = auto_spawn;
+    }
+    fn set_director_parameter(&mut self, name: String, value: f32) {
+        let world = self.planner.mut_world();
+        if let Err(e) = world.write_resource::<resources::Director>().parameters.set(&*name, value) {
+            println!("set_director_parameter: {}", e);
+        }
+    }
+    fn set_director_stress(&mut self, stress: f32) {
+        self.planner.mut_world().write_resource::<resources::Director>().stress = stress.max(0.).min(1.);
+    }
+    fn set_director_phase(&mut self, phase: String) {
+        let world = self.planner.mut_world();
+        match resources::DirectorPhase::from_str(&*phase) {
+            Some(phase) => world.write_resource::<resources::Director>().set_phase(phase),
+            None => println!("set_director_phase: unknown phase '{}'", phase),
+        }
+    }
+    fn inspect_director(&mut self) {
+        let world = self.planner.mut_world();
+        let director = world.read_resource::<resources::Director>();
+        let parameters = director.parameters.values().iter()
+            .map(|&(ref name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>();
+        let ref mut callbacks = world.write_resource::<resources::LuaCallbacks>().0;
+        callbacks.push(format!("director_state({},{},\"{}\",{},{{{}}})",
+                               director.enabled, director.stress, director.phase.name(),
+                               director.calm, parameters.join(",")));
+    }
     fn set_zoom(&mut self, new_zoom: f32) {
         let world = self.planner.mut_world();
         let ref mut zoom = world.write_resource::<resources::Zoom>().0;
         *zoom = new_zoom;
     }
-    // fn set_player_shoot(&mut self, shoot: bool) {
-    //     let world = self.planner.mut_world();
-    //     let mut shoots = world.write::<components::Shoot>();
-    //     let players = world.read::<components::PlayerControl>();
-
-    //     for (_, entity) in (&players, &world.entities()).iter() {
-    //         if shoot {
-    //             shoots.insert(entity, components::Shoot);
-    //         } else {
-    //             shoots.remove(entity);
-    //         }
-    //     }
-    // }
-    // fn set_player_weapon(&mut self, kind: String, reload: f32, setup: f32, setdown: f32) {
-    //     if let Some(kind) = weapons::Kind::from_str(&*kind) {
-    //         let next_weapon = components::NextWeapon(components::Weapon {
-    //             kind: kind,
-    //             state: weapons::State::Setup(0.),
-    //             reload_factor: 1./reload,
-    //             setup_factor: 1./setup,
-    //             setdown_factor: 1./setdown,
-    //         });
-
-    //         let world = self.planner.mut_world();
-    //         let mut next_weapons = world.write::<components::NextWeapon>();
-    //         let players = world.read::<components::PlayerControl>();
-
-    //         for (_, entity) in (&players, &world.entities()).iter() {
-    //             next_weapons.insert(entity, next_weapon.clone());
-    //         }
-    //     }
-    // }
+    fn explode(&mut self, x: f32, y: f32, radius: f32, damage: f32, impulse: f32) {
+        let world = self.planner.mut_world();
+        world.write_resource::<resources::Explosions>().0.push(resources::Explosion {
+            pos: [x, y],
+            radius: radius,
+            damage: damage,
+            impulse: impulse,
+            source: None,
+        });
+    }
+    fn set_projectile_explosion(&mut self, radius: f32, impulse: f32) {
+        let world = self.planner.mut_world();
+        let mut projectile_explosion = world.write_resource::<resources::ProjectileExplosion>();
+        projectile_explosion.radius = radius;
+        projectile_explosion.impulse = impulse;
+    }
+    fn set_door_state(&mut self, door: u32, state: String) {
+        let state = match DoorState::from_str(&*state) {
+            Some(state) => state,
+            None => {
+                println!("set_door_state: unknown state '{}'", state);
+                return;
+            }
+        };
+        let world = self.planner.mut_world();
+        if let Some(entity) = entities::entity_from_id(world, "set_door_state", door) {
+            world.write_resource::<resources::DoorRequests>().0.push((entity, state));
+        }
+    }
+    fn give_player_key(&mut self, key: String) {
+        let world = self.planner.mut_world();
+        let mut keys = world.write::<components::Keys>();
+        let players = world.read::<components::PlayerControl>();
+
+        for (player, entity) in (&players, &world.entities()).iter() {
+            if player.0 == self.active_player {
+                match keys.get_mut(entity) {
+                    Some(keys) => keys.0.push(key.clone()),
+                    None => { keys.insert(entity, components::Keys(vec!(key.clone()))); }
+                }
+            }
+        }
+    }
+    fn set_field(&mut self, entity: u32, strength: f32, radius: f32, falloff: String, group: u32, mask: u32) {
+        let falloff = match physics::Falloff::from_str(&*falloff) {
+            Some(falloff) => falloff,
+            None => {
+                println!("set_field: unknown falloff '{}'", falloff);
+                return;
+            }
+        };
+        let world = self.planner.mut_world();
+        if let Some(entity) = entities::entity_from_id(world, "set_field", entity) {
+            world.write::<components::PhysicField>().insert(entity, components::PhysicField {
+                strength: strength,
+                radius: radius,
+                falloff: falloff,
+                group: group,
+                mask: mask,
+            });
+        }
+    }
+    fn remove_field(&mut self, entity: u32) {
+        let world = self.planner.mut_world();
+        if let Some(entity) = entities::entity_from_id(world, "remove_field", entity) {
+            world.write::<components::PhysicField>().remove(entity);
+        }
+    }
+    fn set_hitscan(&mut self, kind: String, damage: f32, range: f32, rays: u32, spread: f32, penetration: u32) {
+        match weapons::Kind::from_str(&*kind) {
+            Some(kind) => {
+                let world = self.planner.mut_world();
+                let mut hitscans = world.write_resource::<resources::Hitscans>();
+                *hitscans.get_mut(&kind) = weapons::resources::Hitscan {
+                    damage: damage,
+                    range: range,
+                    rays: rays,
+                    spread: spread,
+                    penetration: penetration,
+                };
+            }
+            None => println!("set_hitscan: unknown kind '{}'", kind),
+        }
+    }
+    fn set_player_armor(&mut self, points: f32, bullet: f32, explosion: f32, melee: f32) {
+        let world = self.planner.mut_world();
+        let mut armors = world.write::<components::Armor>();
+        let players = world.read::<components::PlayerControl>();
+
+        for (player, entity) in (&players, &world.entities()).iter() {
+            if player.0 == self.active_player {
+                armors.insert(entity, components::Armor {
+                    points: points,
+                    bullet: bullet,
+                    explosion: explosion,
+                    melee: melee,
+                });
+            }
+        }
+    }
+    fn set_player_shoot(&mut self, shoot: bool) {
+        let world = self.planner.mut_world();
+        let mut shoots = world.write::<components::Shoot>();
+        let players = world.read::<components::PlayerControl>();
+
+        for (player, entity) in (&players, &world.entities()).iter() {
+            if player.0 != self.active_player {
+                continue;
+            }
+            // a null storage can't be written over
+            if shoot && shoots.get(entity).is_none() {
+                shoots.insert(entity, components::Shoot);
+            } else if !shoot {
+                shoots.remove(entity);
+            }
+        }
+    }
+    fn set_player_weapon(&mut self, kind: String, reload: f32, setup: f32, setdown: f32) {
+        if let Some(kind) = weapons::Kind::from_str(&*kind) {
+            let next_weapon = components::NextWeapon(components::Weapon::new(kind, reload, setup, setdown));
+
+            let world = self.planner.mut_world();
+            let mut next_weapons = world.write::<components::NextWeapon>();
+            let players = world.read::<components::PlayerControl>();
+
+            for (player, entity) in (&players, &world.entities()).iter() {
+                if player.0 == self.active_player {
+                    next_weapons.insert(entity, next_weapon.clone());
+                }
+            }
+        } else {
+            println!("set_player_weapon: unknown kind '{}'", kind);
+        }
+    }
 }
@@ -1,12 +1,66 @@
 pub use ::physics::components::*;
+pub use ::monsters::components::*;
+pub use ::weapons::components::*;
+pub use ::health::components::*;
+pub use ::projectiles::components::*;
+pub use ::doors::components::*;
+
+/// Call the macro with the list of components that are part of the game state
+/// (snapshot, save): each one with the name used in save files
+macro_rules! game_components {
+    ($macro:ident) => {
+        $macro! {
+            physic_state: PhysicState,
+            physic_type: PhysicType,
+            physic_damping: PhysicDamping,
+            physic_forces: PhysicForces,
+            physic_torque: PhysicTorque,
+            physic_angular_damping: PhysicAngularDamping,
+            physic_dynamic: PhysicDynamic,
+            physic_static: PhysicStatic,
+            physic_bullet: PhysicBullet,
+            physic_field: PhysicField,
+            draw_physic: DrawPhysic,
+            player_control: PlayerControl,
+            zombie: Zombie,
+            spider: Spider,
+            spider_spawner: SpiderSpawner,
+            aim: Aim,
+            shoot: Shoot,
+            weapon: Weapon,
+            next_weapon: NextWeapon,
+            pickup: Pickup,
+            health: Health,
+            armor: Armor,
+            projectile: Projectile,
+            door: Door,
+            keys: Keys,
+            collision_callback: CollisionCallback,
+        }
+    }
+}
 
 pub fn register_components(world: &mut ::specs::World) {
     ::physics::components::register_components(world);
+    ::monsters::components::register_components(world);
+    ::weapons::components::register_components(world);
+    ::health::components::register_components(world);
+    ::projectiles::components::register_components(world);
+    ::doors::components::register_components(world);
     world.register::<PlayerControl>();
+    world.register::<CollisionCallback>();
 }
 
-#[derive(Clone, Default)]
-pub struct PlayerControl;
+/// Entity controlled by the player of this id
+#[derive(Clone)]
+pub struct PlayerControl(pub u32);
 impl ::specs::Component for PlayerControl {
+    type Storage = ::specs::HashMapStorage<Self>;
+}
+
+/// Entity whose contacts are given to the Lua function collision
+#[derive(Clone, Copy, Default)]
+pub struct CollisionCallback;
+impl ::specs::Component for CollisionCallback {
     type Storage = ::specs::NullStorage<Self>;
 }
@@ -0,0 +1,398 @@
+//! AI director pacing enemy and pickup spawns
+//!
+//! The director tracks the stress of the players: it increases when damage is
+//! applied to a hero and while enemies are nearby, and decays otherwise.
+//! It cycles through phases:
+//!
+//! * build up: enemies are spawned until stress reaches peak_stress
+//! * peak: no spawn during peak_time
+//! * relax: a weapon pickup is spawned, then no spawn until stress is below
+//!   relax_stress and no fight happened for relax_time. The pickup stays
+//!   pending until a position is found for it
+//!
+//! Spawns are placed out of sight of heroes and reachable from them,
+//! on spawn points if the level has some. Each decision is given to the Lua
+//! function director_spawn, enemies and pickups are also built if auto_spawn is set.
+
+pub mod resources {
+    impl_resource! {
+        Director,
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    pub enum DirectorPhase {
+        BuildUp,
+        Peak,
+        Relax,
+    }
+
+    impl DirectorPhase {
+        pub fn name(&self) -> &'static str {
+            match *self {
+                DirectorPhase::BuildUp => "build_up",
+                DirectorPhase::Peak => "peak",
+                DirectorPhase::Relax => "relax",
+            }
+        }
+        pub fn from_str(s: &str) -> Option<DirectorPhase> {
+            match s {
+                "build_up" => Some(DirectorPhase::BuildUp),
+                "peak" => Some(DirectorPhase::Peak),
+                "relax" => Some(DirectorPhase::Relax),
+                _ => None,
+            }
+        }
+    }
+
+    macro_rules! director_parameters {
+        ($($(#[doc = $doc:expr])* $name:ident: $default:expr,)*) => {
+            /// Tuning of the director, each one can be set from Lua by its name
+            #[derive(Clone)]
+            pub struct DirectorParameters {
+                $($(#[doc = $doc])* pub $name: f32,)*
+            }
+            impl DirectorParameters {
+                pub fn new() -> Self {
+                    DirectorParameters {
+                        $($name: $default,)*
+                    }
+                }
+                pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+                    match name {
+                        $(stringify!($name) => self.$name = value,)*
+                        _ => return Err(format!("unknown director parameter '{}'", name)),
+                    }
+                    Ok(())
+                }
+                pub fn values(&self) -> Vec<(String, f32)> {
+                    vec!($((String::from(stringify!($name)), self.$name)),*)
+                }
+            }
+        }
+    }
+
+    director_parameters! {
+        /// added for each damage applied to a hero
+        stress_per_hit: 0.2,
+        /// added each second for each enemy near a hero
+        stress_per_enemy: 0.02,
+        /// removed each second
+        stress_decay: 0.05,
+        /// distance at which an enemy is near a hero
+        nearby_distance: 8.,
+        peak_stress: 0.8,
+        peak_time: 5.,
+        relax_stress: 0.3,
+        /// time without fight before building up again
+        relax_time: 10.,
+        /// enemies per second during build up
+        enemy_rate: 0.5,
+        max_enemies: 20.,
+        spawn_min_distance: 10.,
+        spawn_max_distance: 25.,
+        zombie_walk_velocity: 2.,
+        zombie_run_velocity: 6.,
+        zombie_time_to_reach_vmax: 0.2,
+        zombie_weight: 1.,
+        zombie_vision: 10.,
+        zombie_alert_time: 1.,
+        zombie_decay_time: 3.,
+        pickup_reload: 0.4,
+        pickup_setup: 1.,
+        pickup_setdown: 1.,
+    }
+
+    #[derive(Clone)]
+    pub struct Director {
+        pub enabled: bool,
+        /// if false decisions are only given to Lua, which builds what it wants
+        pub auto_spawn: bool,
+        /// in [0,1]
+        pub stress: f32,
+        pub phase: DirectorPhase,
+        /// time spent in the phase
+        pub phase_time: f32,
+        /// time since the last fight
+        pub calm: f32,
+        /// progress toward the next enemy spawn
+        pub spawn_timer: f32,
+        /// a pickup must be spawned
+        pub pending_pickup: bool,
+        /// number of decisions taken, seeds their random generator
+        pub decisions: u32,
+        pub parameters: DirectorParameters,
+    }
+    impl Director {
+        pub fn new() -> Self {
+            Director {
+                enabled: false,
+                auto_spawn: true,
+                stress: 0.,
+                phase: DirectorPhase::BuildUp,
+                phase_time: 0.,
+                calm: 0.,
+                spawn_timer: 0.,
+                pending_pickup: false,
+                decisions: 0,
+                parameters: DirectorParameters::new(),
+            }
+        }
+        pub fn set_phase(&mut self, phase: DirectorPhase) {
+            self.phase = phase;
+            self.phase_time = 0.;
+        }
+    }
+}
+
+pub mod update_systems {
+    use specs::{self, Join};
+    use utils::Rng;
+    use components::*;
+    use entities::{EntityBuilderMsg, WALL_GROUP};
+    use physics::{Shape, ShapeCast};
+    use physics::navigation::line_of_sight;
+    use resources::{Damages, EntitySpawns, LuaCallbacks, Navigation, PhysicWorld, SpawnPoints};
+
+    use super::resources::*;
+
+    /// Random positions tried when the level has no spawn points
+    const SPAWN_ATTEMPTS: usize = 16;
+    /// Radius of spawned entities
+    const SPAWN_RADIUS: f32 = 0.5;
+    /// Kinds of weapon pickups
+    const PICKUP_KINDS: [&'static str; 4] = ["sniper", "shotgun", "hammer", "uzis"];
+
+    /// Out of sight of heroes, at distance of the closest one and reachable from it,
+    /// heroes at a non finite position are ignored
+    fn valid_position(pos: [f32; 2], heroes: &[[f32; 2]], parameters: &DirectorParameters,
+                      physic_world: &PhysicWorld, navigation: &mut Navigation) -> bool {
+        let finite = |p: &[f32; 2]| p[0].is_finite() && p[1].is_finite();
+        if !finite(&pos) {
+            return false;
+        }
+        let heroes = heroes.iter().cloned().filter(&finite).collect::<Vec<_>>();
+        let distance = |hero: &[f32; 2]| ((hero[0] - pos[0]).powi(2) + (hero[1] - pos[1]).powi(2)).sqrt();
+        let closest = match heroes.iter().min_by(|a, b| distance(a).total_cmp(&distance(b))) {
+            Some(closest) => *closest,
+            None => return false,
+        };
+        let d = distance(&closest);
+        if d < parameters.spawn_min_distance || d > parameters.spawn_max_distance {
+            return false;
+        }
+        if heroes.iter().any(|&hero| line_of_sight(physic_world, hero, pos, 0., WALL_GROUP)) {
+            return false;
+        }
+        let shape_cast = ShapeCast {
+            pos: pos,
+            shape: Shape::Circle(SPAWN_RADIUS),
+            mask: WALL_GROUP,
+            group: !0,
+            not: vec!(),
+        };
+        let mut blocked = false;
+        physic_world.apply_on_shape(&shape_cast, &mut |_, _| blocked = true);
+        !blocked && navigation.find_path(physic_world, closest, pos, SPAWN_RADIUS, WALL_GROUP).is_some()
+    }
+
+    fn spawn_position(rng: &mut Rng, heroes: &[[f32; 2]], parameters: &DirectorParameters, spawn_points: &[[f32; 2]],
+                      physic_world: &PhysicWorld, navigation: &mut Navigation) -> Option<[f32; 2]> {
+        if !spawn_points.is_empty() {
+            let valid = spawn_points.iter()
+                .cloned()
+                .filter(|&point| valid_position(point, heroes, parameters, physic_world, navigation))
+                .collect::<Vec<_>>();
+            if valid.is_empty() {
+                return None;
+            }
+            return Some(valid[rng.below(valid.len() as u32) as usize]);
+        }
+        for _ in 0..SPAWN_ATTEMPTS {
+            let hero = heroes[rng.below(heroes.len() as u32) as usize];
+            let angle = rng.next_f32() * 2. * ::std::f32::consts::PI;
+            let distance = parameters.spawn_min_distance
+                + rng.next_f32() * (parameters.spawn_max_distance - parameters.spawn_min_distance);
+            let pos = [hero[0] + distance * angle.cos(), hero[1] + distance * angle.sin()];
+            if valid_position(pos, heroes, parameters, physic_world, navigation) {
+                return Some(pos);
+            }
+        }
+        None
+    }
+
+    pub struct DirectorSystem;
+    impl specs::System<::utils::UpdateContext> for DirectorSystem {
+        fn run(&mut self, arg: specs::RunArg, context: ::utils::UpdateContext) {
+            let (mut director, mut navigation, mut spawns, mut callbacks, physic_world, spawn_points, damages, player_controls, zombies, spiders, states, entities) = arg.fetch(|world| {
+                (
+                    world.write_resource::<Director>(),
+                    world.write_resource::<Navigation>(),
+                    world.write_resource::<EntitySpawns>(),
+                    world.write_resource::<LuaCallbacks>(),
+                    world.read_resource::<PhysicWorld>(),
+                    world.read_resource::<SpawnPoints>(),
+                    world.read_resource::<Damages>(),
+                    world.read::<PlayerControl>(),
+                    world.read::<Zombie>(),
+                    world.read::<Spider>(),
+                    world.read::<PhysicState>(),
+                    world.entities(),
+                )
+            });
+
+            if !director.enabled {
+                return;
+            }
+            let dt = context.dt;
+
+            let heroes = (&player_controls, &states).iter()
+                .map(|(_, state)| state.pos)
+                .collect::<Vec<_>>();
+            if heroes.is_empty() {
+                return;
+            }
+            let is_enemy = |entity| zombies.get(entity).is_some() || spiders.get(entity).is_some();
+
+            // stress
+            let mut fight = false;
+            let mut stress = -director.parameters.stress_decay * dt;
+            for damage in &damages.0 {
+                if damage.amount > 0. && player_controls.get(damage.target).is_some() {
+                    stress += director.parameters.stress_per_hit;
+                    fight = true;
+                }
+            }
+            let mut enemies = 0;
+            for (state, entity) in (&states, &entities).iter() {
+                if !is_enemy(entity) {
+                    continue;
+                }
+                enemies += 1;
+                let nearby = heroes.iter().any(|hero| {
+                    (hero[0] - state.pos[0]).powi(2) + (hero[1] - state.pos[1]).powi(2)
+                        < director.parameters.nearby_distance.powi(2)
+                });
+                if nearby {
+                    stress += director.parameters.stress_per_enemy * dt;
+                    fight = true;
+                }
+            }
+            director.stress = (director.stress + stress).max(0.).min(1.);
+            director.calm = if fight { 0. } else { director.calm + dt };
+            director.phase_time += dt;
+
+            // phase
+            let previous_phase = director.phase;
+            match director.phase {
+                DirectorPhase::BuildUp => if director.stress >= director.parameters.peak_stress {
+                    director.set_phase(DirectorPhase::Peak);
+                },
+                DirectorPhase::Peak => if director.phase_time >= director.parameters.peak_time {
+                    director.set_phase(DirectorPhase::Relax);
+                },
+                DirectorPhase::Relax => {
+                    if director.stress <= director.parameters.relax_stress && director.calm >= director.parameters.relax_time {
+                        director.set_phase(DirectorPhase::BuildUp);
+                    }
+                }
+            }
+            if director.phase != previous_phase {
+                callbacks.0.push(format!("director_phase(\"{}\",{})", director.phase.name(), director.stress));
+            }
+
+            // spawns
+            let mut decisions = vec!();
+            if director.phase == DirectorPhase::BuildUp && (enemies as f32) < director.parameters.max_enemies {
+                director.spawn_timer += director.parameters.enemy_rate * dt;
+                if director.spawn_timer >= 1. {
+                    director.spawn_timer -= 1.;
+                    decisions.push("zombie");
+                }
+            }
+            if director.phase == DirectorPhase::Relax && previous_phase != DirectorPhase::Relax {
+                director.pending_pickup = true;
+            }
+            if director.pending_pickup {
+                decisions.push("pickup");
+            }
+            for kind in decisions {
+                let mut rng = Rng::new(director.decisions);
+                director.decisions = director.decisions.wrapping_add(1);
+                let pos = match spawn_position(&mut rng, &heroes, &director.parameters, &spawn_points.0, &physic_world, &mut navigation) {
+                    Some(pos) => pos,
+                    None => continue,
+                };
+                callbacks.0.push(format!("director_spawn(\"{}\",{},{})", kind, pos[0], pos[1]));
+                if kind == "pickup" {
+                    director.pending_pickup = false;
+                }
+                if !director.auto_spawn {
+                    continue;
+                }
+                let p = &director.parameters;
+                if kind == "zombie" {
+                    spawns.0.push(EntityBuilderMsg::add_zombie((), pos[0], pos[1],
+                        p.zombie_walk_velocity, p.zombie_run_velocity, p.zombie_time_to_reach_vmax,
+                        p.zombie_weight, p.zombie_vision, p.zombie_alert_time, p.zombie_decay_time));
+                } else {
+                    let weapon = PICKUP_KINDS[rng.below(PICKUP_KINDS.len() as u32) as usize];
+                    spawns.0.push(EntityBuilderMsg::add_pickup((), pos[0], pos[1], String::from(weapon),
+                        p.pickup_reload, p.pickup_setup, p.pickup_setdown));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_in_open_level_test() {
+        use physics::EntityInformation;
+
+        let mut world = specs::World::new();
+        let wall = world.create_now().build();
+        let mut physic_world = PhysicWorld::new();
+        let mut navigation = Navigation::new();
+        let parameters = DirectorParameters::new();
+        let heroes = [[0., 0.]];
+
+        // a single wall hiding the hero, the rest of the level is open
+        physic_world.insert_static(EntityInformation {
+            entity: wall,
+            pos: [5., 0.],
+            group: WALL_GROUP,
+            mask: !0,
+            shape: Shape::Rectangle(1., 6.),
+        });
+
+        // far behind the wall
+        assert!(valid_position([20., 0.], &heroes, &parameters, &physic_world, &mut navigation));
+        // in sight of the hero
+        assert!(!valid_position([0., 20.], &heroes, &parameters, &physic_world, &mut navigation));
+
+        let mut rng = Rng::new(0);
+        let spawn_points = [[0., 20.], [20., 0.]];
+        assert_eq!(Some([20., 0.]), spawn_position(&mut rng, &heroes, &parameters, &spawn_points, &physic_world, &mut navigation));
+    }
+
+    #[test]
+    fn non_finite_hero_test() {
+        use physics::EntityInformation;
+
+        let mut world = specs::World::new();
+        let wall = world.create_now().build();
+        let mut physic_world = PhysicWorld::new();
+        physic_world.insert_static(EntityInformation {
+            entity: wall,
+            pos: [5., 0.],
+            group: WALL_GROUP,
+            mask: !0,
+            shape: Shape::Rectangle(1., 6.),
+        });
+        let mut navigation = Navigation::new();
+        let parameters = DirectorParameters::new();
+        let nan = ::std::f32::NAN;
+
+        assert!(valid_position([20., 0.], &[[nan, 0.], [0., 0.]], &parameters, &physic_world, &mut navigation));
+        assert!(!valid_position([20., 0.], &[[nan, nan]], &parameters, &physic_world, &mut navigation));
+        assert!(!valid_position([nan, 0.], &[[0., 0.]], &parameters, &physic_world, &mut navigation));
+    }
+}
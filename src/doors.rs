@@ -0,0 +1,184 @@
+//! Doors opened by keys or triggers
+//!
+//! A locked door opens when a hero holding its key touches it, a closed door
+//! is opened by a trigger: Lua calls set_door_state, usually from
+//! sensor_enter. An open door has no PhysicStatic component so it isn't in
+//! the physic world: the change is made in PhysicWorld at once.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DoorState {
+    Open,
+    Closed,
+    Locked,
+}
+
+impl DoorState {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DoorState::Open => "open",
+            DoorState::Closed => "closed",
+            DoorState::Locked => "locked",
+        }
+    }
+    pub fn from_str(s: &str) -> Option<DoorState> {
+        match s {
+            "open" => Some(DoorState::Open),
+            "closed" => Some(DoorState::Closed),
+            "locked" => Some(DoorState::Locked),
+            _ => None,
+        }
+    }
+    pub fn color(&self) -> [f32; 4] {
+        match *self {
+            DoorState::Open => [0.6, 0.4, 0.2, 0.3],
+            DoorState::Closed => [0.6, 0.4, 0.2, 1.],
+            DoorState::Locked => [0.4, 0.1, 0.1, 1.],
+        }
+    }
+}
+
+pub mod components {
+    use super::DoorState;
+
+    impl_component! {
+        Door: HashMapStorage,
+        Keys: HashMapStorage,
+    }
+
+    #[derive(Clone)]
+    pub struct Door {
+        pub state: DoorState,
+        /// unlocks the door
+        pub key: String,
+    }
+
+    /// Keys held by a hero
+    #[derive(Clone)]
+    pub struct Keys(pub Vec<String>);
+}
+
+pub mod resources {
+    use specs;
+    use super::DoorState;
+
+    impl_resource! {
+        DoorRequests,
+    }
+
+    /// Changes of state of doors to make during the next update
+    pub struct DoorRequests(pub Vec<(specs::Entity, DoorState)>);
+    impl DoorRequests {
+        pub fn new() -> Self {
+            DoorRequests(Vec::new())
+        }
+    }
+}
+
+pub mod update_systems {
+    use specs;
+    use components::*;
+    use physics::{ContactState, EntityInformation};
+    use resources::{CollisionEvents, LuaCallbacks, PhysicWorld};
+
+    use super::DoorState;
+    use super::resources::*;
+
+    pub struct DoorSystem;
+    impl specs::System<::utils::UpdateContext> for DoorSystem {
+        fn run(&mut self, arg: specs::RunArg, _context: ::utils::UpdateContext) {
+            let (mut doors, mut statics, mut draws, keys, states, types, mut requests, mut physic_world, mut callbacks, collision_events) = arg.fetch(|world| {
+                (
+                    world.write::<Door>(),
+                    world.write::<PhysicStatic>(),
+                    world.write::<DrawPhysic>(),
+                    world.read::<Keys>(),
+                    world.read::<PhysicState>(),
+                    world.read::<PhysicType>(),
+                    world.write_resource::<DoorRequests>(),
+                    world.write_resource::<PhysicWorld>(),
+                    world.write_resource::<LuaCallbacks>(),
+                    world.read_resource::<CollisionEvents>(),
+                )
+            });
+
+            let mut changes = requests.0.drain(..).collect::<Vec<_>>();
+            for event in &collision_events.events {
+                // persisting contacts too: the key may be given while touching the door
+                if event.state == ContactState::Ended || event.sensor {
+                    continue;
+                }
+                for &(door_entity, other) in &[(event.a, event.b), (event.b, event.a)] {
+                    let door = match doors.get(door_entity) {
+                        Some(door) => door,
+                        None => continue,
+                    };
+                    let unlocked = keys.get(other).map_or(false, |keys| keys.0.contains(&door.key));
+                    if door.state == DoorState::Locked && unlocked {
+                        changes.push((door_entity, DoorState::Open));
+                    }
+                }
+            }
+
+            for (entity, state) in changes {
+                let door = match doors.get_mut(entity) {
+                    Some(door) => door,
+                    None => continue,
+                };
+                if door.state == state {
+                    continue;
+                }
+                if state == DoorState::Open {
+                    statics.remove(entity);
+                    physic_world.remove_static(entity);
+                } else if door.state == DoorState::Open {
+                    let physic_state = states.get(entity).unwrap();
+                    let typ = types.get(entity).unwrap();
+                    statics.insert(entity, PhysicStatic);
+                    physic_world.insert_static(EntityInformation {
+                        entity: entity,
+                        pos: physic_state.pos,
+                        group: typ.group,
+                        mask: typ.mask,
+                        shape: typ.shape.rotated(physic_state.angle),
+                    });
+                }
+                door.state = state;
+                if let Some(draw) = draws.get_mut(entity) {
+                    draw.color = state.color();
+                }
+                callbacks.0.push(format!("door_changed({},\"{}\")", entity.get_id(), state.name()));
+            }
+        }
+    }
+
+    #[test]
+    fn door_state_test() {
+        use physics::navigation::line_of_sight;
+        use entities::{add_door, WALL_GROUP};
+        use specs::Join;
+
+        let mut world = specs::World::new();
+        ::resources::add_resources(&mut world);
+        register_components(&mut world);
+        add_door(&mut world, 0., 0., 1., 4., 10., "closed".into(), String::new());
+        world.write_resource::<PhysicWorld>().fill(&world);
+        let door = (&world.read::<Door>(), &world.entities()).iter().next().unwrap().1;
+        let cells = world.read_resource::<PhysicWorld>().inert.len();
+
+        let mut planner = specs::Planner::new(world, 1);
+        planner.add_system(DoorSystem, "door", 0);
+        let mut set_state = |state| {
+            planner.mut_world().write_resource::<DoorRequests>().0.push((door, state));
+            planner.dispatch(::utils::UpdateContext { dt: 0.1 });
+            planner.wait();
+            let physic_world = planner.mut_world().read_resource::<PhysicWorld>();
+            let inert = physic_world.inert.values().flat_map(|infos| infos.iter()).filter(|info| info.entity == door).count();
+            (inert, line_of_sight(&physic_world, [-5., 0.], [5., 0.], 0., WALL_GROUP))
+        };
+
+        assert_eq!(set_state(DoorState::Open), (0, true));
+        assert_eq!(set_state(DoorState::Closed), (cells, false));
+        assert_eq!(set_state(DoorState::Locked), (cells, false));
+        assert_eq!(set_state(DoorState::Open), (0, true));
+    }
+}
@@ -5,10 +5,15 @@ use colors;
 
 use physics::draw_systems::*;
 use notifications::draw_systems::*;
+use weapons::draw_systems::*;
+use explosions::draw_systems::*;
 
 pub fn run(world: &mut specs::World, frame: &mut graphics::Frame) {
     draw_notifications(world, frame);
     draw_physic(world, frame);
+    draw_weapon(world, frame);
+    draw_tracers(world, frame);
+    draw_shockwaves(world, frame);
     draw_cursor(world, frame);
 }
 
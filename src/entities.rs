@@ -1,12 +1,18 @@
-use specs;
+use specs::{self, Join};
 use components::*;
 use physics::{self, Shape, CollisionBehavior};
+use monsters::ZombieState;
+use weapons::Kind;
+use projectiles::Impact;
+use doors::DoorState;
+use resources::NextPlayer;
 use std::f32;
 
 macro_rules! entity_builder {
     ($($entity:ident($($var_name:ident: $var_type:ident),*),)*) => {
         #[allow(non_camel_case_types)]
         #[doc(hidden)]
+        #[derive(Clone)]
         pub enum EntityBuilderMsg {
             $($entity((), $($var_type),*)),*
         }
@@ -52,13 +58,66 @@ macro_rules! entity_builder {
 entity_builder! {
     add_wall(x: f32, y: f32, width: f32, height: f32),
     add_character(x: f32, y: f32, velocity: f32, time_to_reach_v_max: f32, weight: f32),
+    add_trigger(x: f32, y: f32, width: f32, height: f32),
+    add_pickup(x: f32, y: f32, kind: String, reload: f32, setup: f32, setdown: f32),
+    add_rotated_wall(x: f32, y: f32, width: f32, height: f32, angle: f32),
+    add_segment_wall(x0: f32, y0: f32, x1: f32, y1: f32),
+    add_zombie(x: f32, y: f32, walk_velocity: f32, run_velocity: f32, time_to_reach_v_max: f32, weight: f32, vision: f32, alert_time: f32, decay_time: f32),
+    add_spider_spawner(x: f32, y: f32, rate: f32, count: u32, velocity: f32, sight: f32, lifetime: f32, population: u32),
+    add_spider(x: f32, y: f32, angle: f32, velocity: f32, sight: f32, lifetime: f32, population: u32),
+    add_destructible_wall(x: f32, y: f32, width: f32, height: f32, health: f32),
+    add_door(x: f32, y: f32, width: f32, height: f32, health: f32, state: String, key: String),
+    add_projectile(x: f32, y: f32, angle: f32, velocity: f32, radius: f32, lifetime: f32, owner: i32, mask: u32, impact: String, damage: f32),
 }
 
-const WALL_GROUP:  u32 = 0b00000000000000000000000000000001;
-const CHAR_GROUP:  u32 = 0b00000000000000000000000000000010;
+pub const WALL_GROUP:   u32 = 0b00000000000000000000000000000001;
+const CHAR_GROUP:       u32 = 0b00000000000000000000000000000010;
+const TRIGGER_GROUP:    u32 = 0b00000000000000000000000000000100;
+const MONSTER_GROUP:    u32 = 0b00000000000000000000000000001000;
+const SPIDER_GROUP:     u32 = 0b00000000000000000000000000010000;
+const SPAWNER_GROUP:    u32 = 0b00000000000000000000000000100000;
+const PROJECTILE_GROUP: u32 = 0b00000000000000000000000001000000;
+const PICKUP_GROUP:     u32 = 0b00000000000000000000000010000000;
 
-const WALL_MASK:   u32 = 0b11111111111111111111111111111111;
-const CHAR_MASK:   u32 = 0b11111111111111111111111111111111;
+const WALL_MASK:        u32 = 0b11111111111111111111111111111111;
+const CHAR_MASK:        u32 = 0b11111111111111111111111111111111;
+const TRIGGER_MASK:     u32 = CHAR_GROUP;
+const MONSTER_MASK:     u32 = 0b11111111111111111111111111111111;
+const SPIDER_MASK:      u32 = !SPIDER_GROUP;
+const SPAWNER_MASK:     u32 = 0;
+const PICKUP_MASK:      u32 = CHAR_GROUP;
+
+/// Entities hit by shots: sensors are not
+pub const HITSCAN_MASK: u32 = WALL_GROUP | CHAR_GROUP | MONSTER_GROUP | SPIDER_GROUP;
+/// Entities damaged or pushed by explosions
+pub const EXPLOSION_MASK: u32 = HITSCAN_MASK | PROJECTILE_GROUP;
+
+const CHARACTER_HEALTH: f32 = 100.;
+const ZOMBIE_HEALTH: f32 = 50.;
+const SPIDER_HEALTH: f32 = 5.;
+
+const SPIDER_RADIUS: f32 = 0.1;
+const SPIDER_WEIGHT: f32 = 0.1;
+const SPIDER_TIME_TO_REACH_VMAX: f32 = 0.05;
+
+const PROJECTILE_WEIGHT: f32 = 0.01;
+const PROJECTILE_RESTITUTION: f32 = 0.6;
+const PROJECTILE_FRICTION: f32 = 0.2;
+
+const PICKUP_RADIUS: f32 = 0.3;
+
+/// Name of the force of characters and monsters moving by themselves
+pub const MOVE_FORCE: &'static str = "move";
+
+fn move_force(angle: f32, strength: f32, coef: f32) -> PhysicForces {
+    let mut forces = PhysicForces::new();
+    *forces.named(MOVE_FORCE) = Force {
+        angle: angle,
+        strength: strength,
+        coef: coef,
+    };
+    forces
+}
 
 pub fn add_wall(world: &mut specs::World, x: f32, y: f32, width: f32, height: f32) {
     let shape = Shape::Rectangle(width, height);
@@ -73,24 +132,282 @@ pub fn add_wall(world: &mut specs::World, x: f32, y: f32, width: f32, height: f3
         .build();
 }
 
+/// Removed from the physic world when its health is spent
+pub fn add_destructible_wall(world: &mut specs::World, x: f32, y: f32, width: f32, height: f32, health: f32) {
+    let shape = Shape::Rectangle(width, height);
+    world.create_now()
+        .with(PhysicState::new([x, y]))
+        .with(PhysicType::new_static(WALL_GROUP, WALL_MASK, shape))
+        .with(PhysicStatic)
+        .with(Health::new(health))
+        .with(DrawPhysic {
+            color: [0.3, 0.3, 0.3, 1.],
+            border: None,
+        })
+        .build();
+}
+
+/// state: open, closed or locked, see doors.rs. Indestructible if health is 0
+pub fn add_door(world: &mut specs::World, x: f32, y: f32, width: f32, height: f32, health: f32, state: String, key: String) {
+    let state = match DoorState::from_str(&*state) {
+        Some(state) => state,
+        None => {
+            println!("add_door: unknown state '{}'", state);
+            return;
+        }
+    };
+    let shape = Shape::Rectangle(width, height);
+    let entity = world.create_now()
+        .with(PhysicState::new([x, y]))
+        .with(PhysicType::new_static(WALL_GROUP, WALL_MASK, shape))
+        .with(Door {
+            state: state,
+            key: key,
+        })
+        .with(DrawPhysic {
+            color: state.color(),
+            border: None,
+        })
+        .build();
+    if state != DoorState::Open {
+        world.write::<PhysicStatic>().insert(entity, PhysicStatic);
+    }
+    if health > 0. {
+        world.write::<Health>().insert(entity, Health::new(health));
+    }
+}
+
+pub fn add_rotated_wall(world: &mut specs::World, x: f32, y: f32, width: f32, height: f32, angle: f32) {
+    let (w2, h2) = (width / 2., height / 2.);
+    let shape = Shape::Polygon(vec!([-w2, -h2], [w2, -h2], [w2, h2], [-w2, h2]), angle);
+    world.create_now()
+        .with(PhysicState::new([x, y]))
+        .with(PhysicType::new_static(WALL_GROUP, WALL_MASK, shape))
+        .with(PhysicStatic)
+        .with(DrawPhysic {
+            color: [0., 0., 0., 1.],
+            border: None,
+        })
+        .build();
+}
+
+pub fn add_segment_wall(world: &mut specs::World, x0: f32, y0: f32, x1: f32, y1: f32) {
+    let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+    let shape = Shape::Segment(length, (y1 - y0).atan2(x1 - x0));
+    world.create_now()
+        .with(PhysicState::new([(x0 + x1) / 2., (y0 + y1) / 2.]))
+        .with(PhysicType::new_static(WALL_GROUP, WALL_MASK, shape))
+        .with(PhysicStatic)
+        .with(DrawPhysic {
+            color: [0., 0., 0., 1.],
+            border: None,
+        })
+        .build();
+}
+
+/// Each character is controlled by a new player: the first one is player 0
 pub fn add_character(world: &mut specs::World, x: f32, y: f32, velocity: f32, time_to_reach_vmax: f32, weight: f32) {
+    let player = {
+        let mut next_player = world.write_resource::<NextPlayer>();
+        next_player.0 += 1;
+        next_player.0 - 1
+    };
     let shape = Shape::Circle(0.5);
     let (force, damping) = physics::compute_force_damping(velocity, time_to_reach_vmax, weight);
     world.create_now()
         .with(PhysicState::new([x, y]))
         .with(PhysicType::new_movable(CHAR_GROUP, CHAR_MASK, shape, CollisionBehavior::Persist, weight))
-        .with(PhysicForce {
-            angle: 0.,
-            strength: 0.,
-            coef: force,
-        })
+        .with(move_force(0., 0., force))
         .with(PhysicDamping(damping))
         .with(PhysicDynamic)
-        .with(PlayerControl)
-        // .with(Aim(0.0))
+        .with(PlayerControl(player))
+        .with(Health::new(CHARACTER_HEALTH))
+        .with(Keys(vec!()))
+        .with(Aim(0.0))
         .with(DrawPhysic {
             color: [1., 1., 1., 1.],
             border: Some((0.15, [0., 0., 0., 1.])),
         })
         .build();
 }
+
+/// Zombie starts idle, see monsters::ZombieSystem
+pub fn add_zombie(world: &mut specs::World, x: f32, y: f32, walk_velocity: f32, run_velocity: f32, time_to_reach_vmax: f32, weight: f32, vision: f32, alert_time: f32, decay_time: f32) {
+    let shape = Shape::Circle(0.5);
+    let (walk_force, damping) = physics::compute_force_damping(walk_velocity, time_to_reach_vmax, weight);
+    let (run_force, _) = physics::compute_force_damping(run_velocity, time_to_reach_vmax, weight);
+    world.create_now()
+        .with(PhysicState::new([x, y]))
+        .with(PhysicType::new_movable(MONSTER_GROUP, MONSTER_MASK, shape, CollisionBehavior::Persist, weight))
+        .with(move_force(0., 0., walk_force))
+        .with(PhysicDamping(damping))
+        .with(PhysicDynamic)
+        .with(Zombie {
+            state: ZombieState::Idle,
+            alert: 0.,
+            walk_force: walk_force,
+            run_force: run_force,
+            vision: vision,
+            alert_time: alert_time,
+            decay_time: decay_time,
+        })
+        .with(Health::new(ZOMBIE_HEALTH))
+        .with(DrawPhysic {
+            color: [0.3, 0.5, 0.3, 1.],
+            border: Some((0.15, [0., 0., 0., 1.])),
+        })
+        .build();
+}
+
+/// Count and lifetime are unlimited if 0, see monsters::SpiderSystem
+pub fn add_spider_spawner(world: &mut specs::World, x: f32, y: f32, rate: f32, count: u32, velocity: f32, sight: f32, lifetime: f32, population: u32) {
+    world.create_now()
+        .with(PhysicState::new([x, y]))
+        .with(PhysicType::new_sensor(SPAWNER_GROUP, SPAWNER_MASK, Shape::Circle(SPIDER_RADIUS * 3.)))
+        .with(PhysicStatic)
+        .with(SpiderSpawner {
+            rate: rate,
+            timer: 0.,
+            remaining: if count == 0 { None } else { Some(count) },
+            direction: 0,
+            velocity: velocity,
+            sight: sight,
+            lifetime: if lifetime == 0. { None } else { Some(lifetime) },
+            population: population,
+        })
+        .with(DrawPhysic {
+            color: [0.5, 0.5, 0.5, 1.],
+            border: Some((0.05, [0., 0., 0., 1.])),
+        })
+        .build();
+}
+
+/// Lifetime is unlimited if 0
+pub fn add_spider(world: &mut specs::World, x: f32, y: f32, angle: f32, velocity: f32, sight: f32, lifetime: f32, population: u32) {
+    let shape = Shape::Circle(SPIDER_RADIUS);
+    let (force, damping) = physics::compute_force_damping(velocity, SPIDER_TIME_TO_REACH_VMAX, SPIDER_WEIGHT);
+    world.create_now()
+        .with(PhysicState::new([x, y]))
+        .with(PhysicType::new_movable(SPIDER_GROUP, SPIDER_MASK, shape, CollisionBehavior::Persist, SPIDER_WEIGHT))
+        .with(move_force(angle, 1., force))
+        .with(PhysicDamping(damping))
+        .with(PhysicDynamic)
+        .with(Spider {
+            heading: angle,
+            velocity: velocity,
+            sight: sight,
+            lifetime: if lifetime == 0. { None } else { Some(lifetime) },
+            population: population,
+            openings: (true, true),
+        })
+        .with(Health::new(SPIDER_HEALTH))
+        .with(DrawPhysic {
+            color: [0., 0., 0., 1.],
+            border: None,
+        })
+        .build();
+}
+
+/// The alive entity of the id given to Lua, print an error for caller if there is none
+pub fn entity_from_id(world: &specs::World, caller: &str, id: u32) -> Option<specs::Entity> {
+    let entity = (&world.entities()).iter().find(|entity| entity.get_id() == id);
+    if entity.is_none() {
+        println!("{}: no entity {}", caller, id);
+    }
+    entity
+}
+
+/// Move at constant velocity until its first impact or the end of its lifetime,
+/// see projectiles.rs. owner is the id of an entity not hit by the projectile,
+/// none if negative, the projectile should start outside of it.
+/// impact: destroy, bounce or explode, bounce restitution and friction can be
+/// changed with set_bounce
+pub fn add_projectile(world: &mut specs::World, x: f32, y: f32, angle: f32, velocity: f32, radius: f32, lifetime: f32, owner: i32, mask: u32, impact: String, damage: f32) {
+    let impact = match Impact::from_str(&*impact) {
+        Some(impact) => impact,
+        None => {
+            println!("add_projectile: unknown impact '{}'", impact);
+            return;
+        }
+    };
+    let owner = if owner < 0 {
+        None
+    } else {
+        entity_from_id(world, "add_projectile", owner as u32)
+    };
+    // contacts with the owner are resolved without stopping the projectile
+    let collision = match impact {
+        Impact::Bounce => CollisionBehavior::Bounce,
+        _ => CollisionBehavior::Persist,
+    };
+    let mut state = PhysicState::new([x, y]);
+    state.vel = [velocity * angle.cos(), velocity * angle.sin()];
+    state.angle = angle;
+    world.create_now()
+        .with(state)
+        .with(PhysicType::new_movable(PROJECTILE_GROUP, mask, Shape::Circle(radius), collision, PROJECTILE_WEIGHT)
+              .with_bounce(PROJECTILE_RESTITUTION, PROJECTILE_FRICTION))
+        .with(PhysicForces::new())
+        .with(PhysicDynamic)
+        .with(PhysicBullet)
+        .with(Projectile {
+            owner: owner,
+            lifetime: lifetime,
+            impact: impact,
+            damage: damage,
+        })
+        .with(DrawPhysic {
+            color: [0.8, 0.4, 0.1, 1.],
+            border: None,
+        })
+        .build();
+}
+
+pub fn add_trigger(world: &mut specs::World, x: f32, y: f32, width: f32, height: f32) {
+    let shape = Shape::Rectangle(width, height);
+    world.create_now()
+        .with(PhysicState::new([x, y]))
+        .with(PhysicType::new_sensor(TRIGGER_GROUP, TRIGGER_MASK, shape))
+        .with(PhysicStatic)
+        .build();
+}
+
+/// Weapon given to the first hero touching it, see weapons::PickupSystem.
+/// kind: sniper, shotgun, hammer or uzis
+pub fn add_pickup(world: &mut specs::World, x: f32, y: f32, kind: String, reload: f32, setup: f32, setdown: f32) {
+    let kind = match Kind::from_str(&*kind) {
+        Some(kind) => kind,
+        None => {
+            println!("add_pickup: unknown kind '{}'", kind);
+            return;
+        }
+    };
+    world.create_now()
+        .with(PhysicState::new([x, y]))
+        .with(PhysicType::new_sensor(PICKUP_GROUP, PICKUP_MASK, Shape::Circle(PICKUP_RADIUS)))
+        .with(PhysicStatic)
+        .with(Pickup(Weapon::new(kind, reload, setup, setdown)))
+        .with(DrawPhysic {
+            color: [0.8, 0.7, 0.2, 1.],
+            border: Some((0.05, [0., 0., 0., 1.])),
+        })
+        .build();
+}
+
+#[test]
+fn player_id_test() {
+    let mut world = specs::World::new();
+    ::resources::add_resources(&mut world);
+    register_components(&mut world);
+    add_character(&mut world, 0., 0., 1., 1., 1.);
+    add_character(&mut world, 2., 0., 1., 1., 1.);
+    let first = (&world.read::<PlayerControl>(), &world.entities()).iter()
+        .find(|&(player, _)| player.0 == 0)
+        .unwrap().1;
+    world.delete_now(first);
+    add_character(&mut world, 4., 0., 1., 1., 1.);
+
+    let mut players = (&world.read::<PlayerControl>()).iter().map(|player| player.0).collect::<Vec<_>>();
+    players.sort();
+    assert_eq!(players, vec!(1, 2));
+}
@@ -0,0 +1,262 @@
+//! Explosions with area damage and impulse
+//!
+//! Each body of EXPLOSION_MASK overlapping the blast and not hidden by a wall
+//! is damaged and pushed away by an impulse, both decrease linearly with the distance
+//! from the center to the nearest point of the body, down to zero at the radius of the blast.
+//! A shockwave is drawn for each explosion.
+
+pub mod resources {
+    use specs;
+
+    impl_resource! {
+        Explosions,
+        ProjectileExplosion,
+        Shockwaves,
+    }
+
+    #[derive(Clone)]
+    pub struct Explosion {
+        pub pos: [f32; 2],
+        pub radius: f32,
+        /// at the center of the blast
+        pub damage: f32,
+        /// change of momentum at the center of the blast
+        pub impulse: f32,
+        /// entity that caused the explosion
+        pub source: Option<specs::Entity>,
+    }
+
+    /// Explosions to apply during the next update of the explosion system
+    pub struct Explosions(pub Vec<Explosion>);
+    impl Explosions {
+        pub fn new() -> Self {
+            Explosions(Vec::new())
+        }
+    }
+
+    /// Blast of projectiles with explode impact, their damage is the one of the projectile
+    pub struct ProjectileExplosion {
+        pub radius: f32,
+        pub impulse: f32,
+    }
+    impl ProjectileExplosion {
+        pub fn new() -> Self {
+            ProjectileExplosion {
+                radius: 3.,
+                impulse: 5.,
+            }
+        }
+    }
+
+    pub const SHOCKWAVE_DURATION: f32 = 0.3;
+
+    pub struct Shockwave {
+        pub pos: [f32; 2],
+        pub radius: f32,
+        /// time left
+        pub time: f32,
+    }
+
+    pub struct Shockwaves(pub Vec<Shockwave>);
+    impl Shockwaves {
+        pub fn new() -> Self {
+            Shockwaves(Vec::new())
+        }
+    }
+}
+
+pub mod update_systems {
+    use specs;
+    use fnv::FnvHashSet;
+    use components::*;
+    use entities::{EXPLOSION_MASK, WALL_GROUP};
+    use physics::{ContinueOrStop, RayCast, Shape, ShapeCast};
+    use resources::{Damage, Damages, PhysicWorld};
+    use health::DamageKind;
+
+    use super::resources::*;
+
+    /// A wall other than target is between from and to
+    fn occluded(physic_world: &PhysicWorld, from: [f32; 2], to: [f32; 2], target: specs::Entity) -> bool {
+        let length = ((to[0] - from[0]).powi(2) + (to[1] - from[1]).powi(2)).sqrt();
+        if length == 0. {
+            return false;
+        }
+        let ray = RayCast {
+            origin: from,
+            angle: (to[1] - from[1]).atan2(to[0] - from[0]),
+            length: length,
+            mask: WALL_GROUP,
+            group: !0,
+            not: vec!(target),
+        };
+        let mut occluded = false;
+        physic_world.raycast(&ray, &mut |_| {
+            occluded = true;
+            ContinueOrStop::Stop
+        });
+        occluded
+    }
+
+    pub struct ExplosionSystem;
+    impl specs::System<::utils::UpdateContext> for ExplosionSystem {
+        fn run(&mut self, arg: specs::RunArg, context: ::utils::UpdateContext) {
+            let (mut forces, healths, mut explosions, mut shockwaves, mut damages, physic_world) = arg.fetch(|world| {
+                (
+                    world.write::<PhysicForces>(),
+                    world.read::<Health>(),
+                    world.write_resource::<Explosions>(),
+                    world.write_resource::<Shockwaves>(),
+                    world.write_resource::<Damages>(),
+                    world.read_resource::<PhysicWorld>(),
+                )
+            });
+
+            for shockwave in &mut shockwaves.0 {
+                shockwave.time -= context.dt;
+            }
+            shockwaves.0.retain(|shockwave| shockwave.time > 0.);
+
+            for explosion in explosions.0.drain(..) {
+                let shape_cast = ShapeCast {
+                    pos: explosion.pos,
+                    shape: Shape::Circle(explosion.radius),
+                    mask: EXPLOSION_MASK,
+                    group: !0,
+                    not: vec!(),
+                };
+                let mut hits = vec!();
+                let mut visited = FnvHashSet::default();
+                physic_world.apply_on_shape(&shape_cast, &mut |info, collision| {
+                    if visited.insert(info.entity) {
+                        let overlap = (collision.delta_x.powi(2) + collision.delta_y.powi(2)).sqrt();
+                        hits.push((info.clone(), overlap));
+                    }
+                });
+
+                for (info, overlap) in hits {
+                    if occluded(&physic_world, explosion.pos, info.pos, info.entity) {
+                        continue;
+                    }
+                    let dx = info.pos[0] - explosion.pos[0];
+                    let dy = info.pos[1] - explosion.pos[1];
+                    let distance = (dx.powi(2) + dy.powi(2)).sqrt();
+                    // the blast overlaps the body up to the nearest point of the body,
+                    // the overlap is larger than the radius if the center is inside it
+                    let falloff = (overlap / explosion.radius).min(1.);
+
+                    if healths.get(info.entity).is_some() {
+                        damages.0.push(Damage {
+                            target: info.entity,
+                            source: explosion.source,
+                            amount: explosion.damage * falloff,
+                            kind: DamageKind::Explosion,
+                        });
+                    }
+                    if let Some(forces) = forces.get_mut(info.entity) {
+                        if distance > 0. {
+                            let impulse = explosion.impulse * falloff / distance;
+                            forces.add_impulse([impulse * dx, impulse * dy]);
+                        }
+                    }
+                }
+
+                shockwaves.0.push(Shockwave {
+                    pos: explosion.pos,
+                    radius: explosion.radius,
+                    time: SHOCKWAVE_DURATION,
+                });
+            }
+        }
+    }
+
+    /// Damage and impulse of an explosion at the origin on bodies of radius 0.5
+    /// at each position, behind a wall at wall if any
+    #[cfg(test)]
+    fn explode(positions: &[[f32; 2]], wall: Option<[f32; 2]>) -> Vec<(f32, [f32; 2])> {
+        use physics::{CollisionBehavior, EntityInformation};
+
+        let mut world = specs::World::new();
+        ::resources::add_resources(&mut world);
+        register_components(&mut world);
+        let bodies = positions.iter().map(|&pos| {
+            world.create_now()
+                .with(PhysicState::new(pos))
+                .with(PhysicType::new_movable(EXPLOSION_MASK, !0, Shape::Circle(0.5), CollisionBehavior::Persist, 1.))
+                .with(PhysicDynamic)
+                .with(PhysicForces::new())
+                .with(Health::new(100.))
+                .build()
+        }).collect::<Vec<_>>();
+        world.write_resource::<PhysicWorld>().fill(&world);
+        if let Some(pos) = wall {
+            let entity = world.create_now().build();
+            world.write_resource::<PhysicWorld>().insert_static(EntityInformation {
+                entity: entity,
+                pos: pos,
+                group: WALL_GROUP,
+                mask: !0,
+                shape: Shape::Rectangle(0.2, 4.),
+            });
+        }
+        world.write_resource::<Explosions>().0.push(Explosion {
+            pos: [0., 0.],
+            radius: 4.,
+            damage: 10.,
+            impulse: 10.,
+            source: None,
+        });
+
+        let mut planner = specs::Planner::new(world, 1);
+        planner.add_system(ExplosionSystem, "explosion", 0);
+        planner.dispatch(::utils::UpdateContext { dt: 0.1 });
+        planner.wait();
+
+        let world = planner.mut_world();
+        let damages = world.read_resource::<Damages>();
+        let forces = world.read::<PhysicForces>();
+        bodies.iter().map(|&body| {
+            let damage = damages.0.iter().filter(|damage| damage.target == body).map(|damage| damage.amount).sum();
+            (damage, forces.get(body).unwrap().impulse)
+        }).collect()
+    }
+
+    #[test]
+    fn falloff_test() {
+        let hits = explode(&[[1., 0.], [0., -3.], [5., 0.]], None);
+        // the nearest point of the bodies is at 0.5 and 2.5 from the center
+        assert!((hits[0].0 - 8.75).abs() < 1e-4);
+        assert!((hits[0].1[0] - 8.75).abs() < 1e-4 && hits[0].1[1].abs() < 1e-4);
+        assert!((hits[1].0 - 3.75).abs() < 1e-4);
+        assert!(hits[1].1[0].abs() < 1e-4 && (hits[1].1[1] + 3.75).abs() < 1e-4);
+        // out of the blast
+        assert_eq!(hits[2], (0., [0., 0.]));
+    }
+
+    #[test]
+    fn occlusion_test() {
+        let hits = explode(&[[3., 0.], [-3., 0.]], Some([2., 0.]));
+        assert_eq!(hits[0], (0., [0., 0.]));
+        assert!((hits[1].0 - 3.75).abs() < 1e-4);
+        assert!(hits[1].1[0] < 0.);
+    }
+}
+
+pub mod draw_systems {
+    use specs;
+    use graphics::{self, Layer};
+    use colors;
+
+    use super::resources::*;
+
+    /// Disk growing to the radius of the blast while fading
+    pub fn draw_shockwaves(world: &mut specs::World, frame: &mut graphics::Frame) {
+        let shockwaves = world.read_resource::<Shockwaves>();
+        for shockwave in &shockwaves.0 {
+            let progress = 1. - shockwave.time / SHOCKWAVE_DURATION;
+            let mut color = colors::BLACK;
+            color[3] = 0.5 * (1. - progress);
+            frame.draw_circle(shockwave.pos[0], shockwave.pos[1], shockwave.radius * progress, Layer::Middle, color);
+        }
+    }
+}
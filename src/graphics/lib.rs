@@ -108,7 +108,7 @@ macro_rules! obj {
 }
 
 obj! {
-    // sniper: 0,
+    sniper: 0,
 }
 
 fn load_obj(file: &'static str) -> (Vec<Vertex>, Vec<u8>) {
@@ -554,6 +554,39 @@ impl<'a> Frame<'a> {
             .unwrap();
     }
 
+    /// points must describe a convex polygon
+    pub fn draw_polygon(&mut self, points: &[[f32; 2]], layer: Layer, color: [f32; 4]) {
+        let vertices = points.iter().map(|&p| Vertex { position: p }).collect::<Vec<_>>();
+        let z: f32 = layer.into();
+        let uniform = uniform!{
+            z: z,
+            camera: self.camera(layer),
+            color: color,
+        };
+        let vertex_buffer = glium::VertexBuffer::new(&self.graphics.context, &vertices).unwrap();
+
+        self.frame
+            .draw(&vertex_buffer,
+                  &index::NoIndices(index::PrimitiveType::TriangleFan),
+                  &self.graphics.line_program,
+                  &uniform,
+                  &self.graphics.draw_parameters)
+            .unwrap();
+    }
+
+    pub fn draw_line(&mut self, p0: [f32; 2], p1: [f32; 2], width: f32, layer: Layer, color: [f32; 4]) {
+        let dx = p1[0] - p0[0];
+        let dy = p1[1] - p0[1];
+        let length = (dx.powi(2) + dy.powi(2)).sqrt();
+
+        let trans = Transformation::identity()
+            .translate((p0[0] + p1[0]) / 2., (p0[1] + p1[1]) / 2.)
+            .rotate(dy.atan2(dx))
+            .scale(length / 2., width / 2.);
+
+        self.draw_quad(trans, layer, color);
+    }
+
     /// (x,y) correspond to the down-left anchor
     pub fn draw_text(&mut self, x: f32, y: f32, scale: f32, text: &str, layer: Layer, color: [f32; 4]) {
         let glyphs = {
@@ -0,0 +1,241 @@
+//! Health, armor and damage
+//!
+//! Systems push damages in the Damages resource, the DamageSystem applies
+//! them after the physic update: armor absorbs part of each kind of damage
+//! while it has points, entities without health left are deleted and
+//! the source of the last damage is reported as their killer.
+//! Negative damages count as zero, they never heal.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DamageKind {
+    Bullet,
+    Explosion,
+    Melee,
+}
+
+impl DamageKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DamageKind::Bullet => "bullet",
+            DamageKind::Explosion => "explosion",
+            DamageKind::Melee => "melee",
+        }
+    }
+}
+
+pub mod components {
+    use super::DamageKind;
+
+    impl_component! {
+        Health: VecStorage,
+        Armor: HashMapStorage,
+    }
+
+    #[derive(Clone)]
+    pub struct Health {
+        pub current: f32,
+        pub max: f32,
+    }
+    impl Health {
+        pub fn new(max: f32) -> Self {
+            Health {
+                current: max,
+                max: max,
+            }
+        }
+    }
+
+    /// Absorbs a part of the damages of each kind until its points are spent
+    ///
+    /// parts out of [0,1] are clamped when damages are applied
+    #[derive(Clone)]
+    pub struct Armor {
+        pub points: f32,
+        /// part of bullet damages absorbed in [0,1]
+        pub bullet: f32,
+        pub explosion: f32,
+        pub melee: f32,
+    }
+    impl Armor {
+        pub fn absorption(&self, kind: DamageKind) -> f32 {
+            match kind {
+                DamageKind::Bullet => self.bullet,
+                DamageKind::Explosion => self.explosion,
+                DamageKind::Melee => self.melee,
+            }
+        }
+    }
+}
+
+pub mod resources {
+    use specs;
+    use super::DamageKind;
+
+    impl_resource! {
+        Damages,
+    }
+
+    pub struct Damage {
+        pub target: specs::Entity,
+        /// entity that caused the damage
+        pub source: Option<specs::Entity>,
+        pub amount: f32,
+        pub kind: DamageKind,
+    }
+
+    /// Damages to apply at the end of the update
+    pub struct Damages(pub Vec<Damage>);
+    impl Damages {
+        pub fn new() -> Self {
+            Damages(Vec::new())
+        }
+    }
+}
+
+pub mod update_systems {
+    use specs;
+    use components::*;
+    use resources::{Damages, LuaCallbacks, PhysicWorld};
+
+    pub struct DamageSystem;
+    impl specs::System<::utils::UpdateContext> for DamageSystem {
+        fn run(&mut self, arg: specs::RunArg, _context: ::utils::UpdateContext) {
+            let (mut healths, mut armors, dynamics, statics, mut damages, mut physic_world, mut callbacks) = arg.fetch(|world| {
+                (
+                    world.write::<Health>(),
+                    world.write::<Armor>(),
+                    world.read::<PhysicDynamic>(),
+                    world.read::<PhysicStatic>(),
+                    world.write_resource::<Damages>(),
+                    world.write_resource::<PhysicWorld>(),
+                    world.write_resource::<LuaCallbacks>(),
+                )
+            });
+
+            for damage in damages.0.drain(..) {
+                let health = match healths.get_mut(damage.target) {
+                    Some(health) => health,
+                    None => continue,
+                };
+                if health.current <= 0. {
+                    continue;
+                }
+
+                let mut amount = damage.amount.max(0.);
+                if let Some(armor) = armors.get_mut(damage.target) {
+                    let absorption = armor.absorption(damage.kind).max(0.).min(1.);
+                    let absorbed = (amount * absorption).min(armor.points.max(0.));
+                    armor.points -= absorbed;
+                    amount -= absorbed;
+                }
+                health.current -= amount;
+
+                if health.current <= 0. {
+                    if dynamics.get(damage.target).is_some() {
+                        physic_world.remove_dynamic(damage.target);
+                    }
+                    if statics.get(damage.target).is_some() {
+                        physic_world.remove_static(damage.target);
+                    }
+                    arg.delete(damage.target);
+                    let source = damage.source.map_or(-1, |source| source.get_id() as i32);
+                    callbacks.0.push(format!("on_death({},\"{}\",{})", damage.target.get_id(), damage.kind.name(), source));
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn apply_damages(world: specs::World, damages: Vec<(specs::Entity, Option<specs::Entity>, f32, super::DamageKind)>) -> specs::Planner<::utils::UpdateContext> {
+        use resources::Damage;
+
+        let mut planner = specs::Planner::new(world, 1);
+        planner.add_system(DamageSystem, "damage", 0);
+        for (target, source, amount, kind) in damages {
+            planner.mut_world().write_resource::<Damages>().0.push(Damage {
+                target: target,
+                source: source,
+                amount: amount,
+                kind: kind,
+            });
+        }
+        planner.dispatch(::utils::UpdateContext { dt: 0.1 });
+        planner.wait();
+        planner
+    }
+
+    #[cfg(test)]
+    fn test_world() -> specs::World {
+        let mut world = specs::World::new();
+        ::resources::add_resources(&mut world);
+        register_components(&mut world);
+        world
+    }
+
+    #[test]
+    fn armor_test() {
+        use super::DamageKind::*;
+
+        let mut world = test_world();
+        let armor = Armor { points: 100., bullet: 0.5, explosion: 1., melee: 0. };
+        let targets = [Bullet, Explosion, Melee].iter()
+            .map(|&kind| (world.create_now().with(Health::new(100.)).with(armor.clone()).build(), kind))
+            .collect::<Vec<_>>();
+        let exhausted = world.create_now()
+            .with(Health::new(100.))
+            .with(Armor { points: 3., bullet: 1., explosion: 1., melee: 1. })
+            .build();
+        let clamped = world.create_now()
+            .with(Health::new(100.))
+            .with(Armor { points: 100., bullet: 2., explosion: -1., melee: 0. })
+            .build();
+
+        let mut damages = targets.iter().map(|&(target, kind)| (target, None, 10., kind)).collect::<Vec<_>>();
+        damages.push((exhausted, None, 10., Bullet));
+        damages.push((clamped, None, 10., Bullet));
+        damages.push((clamped, None, 10., Explosion));
+        let mut planner = apply_damages(world, damages);
+        let world = planner.mut_world();
+        let healths = world.read::<Health>();
+        let armors = world.read::<Armor>();
+
+        let state = |entity| (healths.get(entity).unwrap().current, armors.get(entity).unwrap().points);
+        assert_eq!(state(targets[0].0), (95., 95.));
+        assert_eq!(state(targets[1].0), (100., 90.));
+        assert_eq!(state(targets[2].0), (90., 100.));
+        assert_eq!(state(exhausted), (93., 0.));
+        assert_eq!(state(clamped), (90., 90.));
+    }
+
+    #[test]
+    fn negative_damage_test() {
+        let mut world = test_world();
+        let target = world.create_now().with(Health::new(10.)).build();
+        let mut planner = apply_damages(world, vec!((target, None, -5., super::DamageKind::Melee)));
+        assert_eq!(planner.mut_world().read::<Health>().get(target).unwrap().current, 10.);
+    }
+
+    #[test]
+    fn on_death_test() {
+        let mut world = test_world();
+        let source = world.create_now().build();
+        let killed = world.create_now().with(Health::new(10.)).build();
+        let unknown = world.create_now().with(Health::new(10.)).build();
+        let damages = vec!(
+            (killed, Some(source), 10., super::DamageKind::Bullet),
+            // already dead: no second callback
+            (killed, None, 10., super::DamageKind::Melee),
+            (unknown, None, 20., super::DamageKind::Explosion),
+        );
+        let mut planner = apply_damages(world, damages);
+        let world = planner.mut_world();
+        world.maintain();
+
+        assert!(!world.is_alive(killed));
+        assert!(!world.is_alive(unknown));
+        assert_eq!(world.read_resource::<LuaCallbacks>().0, vec!(
+            format!("on_death({},\"bullet\",{})", killed.get_id(), source.get_id()),
+            format!("on_death({},\"explosion\",-1)", unknown.get_id()),
+        ));
+    }
+}
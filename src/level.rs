@@ -0,0 +1,145 @@
+//! Declarative level files
+//!
+//! Each line is a keyword followed by its values, `#` starts a comment line:
+//!
+//! ```text
+//! name "first level"
+//! author "thiolliere"
+//! description "a wall and a trigger"
+//! wall X Y WIDTH HEIGHT
+//! rotated_wall X Y WIDTH HEIGHT ANGLE
+//! segment_wall X0 Y0 X1 Y1
+//! trigger X Y WIDTH HEIGHT
+//! spawn X Y
+//! character X Y VELOCITY TIME_TO_REACH_VMAX WEIGHT
+//! ```
+//!
+//! Entities are built with the entity builders, spawn points are returned.
+
+use std::fs::File;
+use std::io::Read;
+
+use specs;
+use entities;
+use resources::Level;
+use save::{split_words, Save, Words};
+
+#[derive(Debug, PartialEq)]
+enum Item {
+    Wall(f32, f32, f32, f32),
+    RotatedWall(f32, f32, f32, f32, f32),
+    SegmentWall(f32, f32, f32, f32),
+    Trigger(f32, f32, f32, f32),
+    Spawn(f32, f32),
+    Character(f32, f32, f32, f32, f32),
+}
+
+fn positive(value: f32, name: &str) -> Result<f32, String> {
+    if value > 0. {
+        Ok(value)
+    } else {
+        Err(format!("{} must be positive", name))
+    }
+}
+
+fn parse_line(keyword: &str, words: &mut Words, level: &mut Level) -> Result<Option<Item>, String> {
+    let item = match keyword {
+        "name" => {
+            level.name = Save::load(words)?;
+            return Ok(None);
+        }
+        "author" => {
+            level.author = Save::load(words)?;
+            return Ok(None);
+        }
+        "description" => {
+            level.description = Save::load(words)?;
+            return Ok(None);
+        }
+        "wall" => Item::Wall(Save::load(words)?,
+                             Save::load(words)?,
+                             positive(Save::load(words)?, "width")?,
+                             positive(Save::load(words)?, "height")?),
+        "rotated_wall" => Item::RotatedWall(Save::load(words)?,
+                                            Save::load(words)?,
+                                            positive(Save::load(words)?, "width")?,
+                                            positive(Save::load(words)?, "height")?,
+                                            Save::load(words)?),
+        "segment_wall" => Item::SegmentWall(Save::load(words)?,
+                                            Save::load(words)?,
+                                            Save::load(words)?,
+                                            Save::load(words)?),
+        "trigger" => Item::Trigger(Save::load(words)?,
+                                   Save::load(words)?,
+                                   positive(Save::load(words)?, "width")?,
+                                   positive(Save::load(words)?, "height")?),
+        "spawn" => Item::Spawn(Save::load(words)?, Save::load(words)?),
+        "character" => Item::Character(Save::load(words)?,
+                                       Save::load(words)?,
+                                       positive(Save::load(words)?, "velocity")?,
+                                       positive(Save::load(words)?, "time to reach vmax")?,
+                                       positive(Save::load(words)?, "weight")?),
+        _ => return Err(format!("unknown keyword '{}'", keyword)),
+    };
+    Ok(Some(item))
+}
+
+/// Parse the text of a level, errors are prefixed by their line number
+fn parse_level(text: &str) -> Result<(Level, Vec<Item>), String> {
+    let mut level = Level::new();
+    let mut items = vec!();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().starts_with('#') {
+            continue;
+        }
+        let error = |e: String| format!("{}: {}", index + 1, e);
+        let mut words = split_words(line).map_err(&error)?.into_iter();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let mut words = Words::new(words.collect());
+        if let Some(item) = parse_line(&*keyword, &mut words, &mut level).map_err(&error)? {
+            items.push(item);
+        }
+        words.end().map_err(&error)?;
+    }
+    Ok((level, items))
+}
+
+/// Build the level in the world and return its spawn points,
+/// the world is unchanged on error
+pub fn load_level(world: &mut specs::World, path: &str) -> Result<Vec<[f32; 2]>, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let (level, items) = parse_level(&*text).map_err(|e| format!("{}:{}", path, e))?;
+
+    let mut spawn_points = vec!();
+    for item in items {
+        match item {
+            Item::Wall(x, y, w, h) => entities::add_wall(world, x, y, w, h),
+            Item::RotatedWall(x, y, w, h, a) => entities::add_rotated_wall(world, x, y, w, h, a),
+            Item::SegmentWall(x0, y0, x1, y1) => entities::add_segment_wall(world, x0, y0, x1, y1),
+            Item::Trigger(x, y, w, h) => entities::add_trigger(world, x, y, w, h),
+            Item::Spawn(x, y) => spawn_points.push([x, y]),
+            Item::Character(x, y, v, t, w) => entities::add_character(world, x, y, v, t, w),
+        }
+    }
+    *world.write_resource::<Level>() = level;
+    Ok(spawn_points)
+}
+
+#[test]
+fn parse_level_test() {
+    let text = "# comment\nname \"first level\"\n\nwall 0 1 2 3\nspawn -1 2.5\n";
+    let (level, items) = parse_level(text).unwrap();
+    assert_eq!(level.name, "first level");
+    assert_eq!(items, vec!(Item::Wall(0., 1., 2., 3.), Item::Spawn(-1., 2.5)));
+
+    assert_eq!(parse_level("wall 0 0 1 1\ndoor 0 0").err(), Some("2: unknown keyword 'door'".into()));
+    assert_eq!(parse_level("spawn 0 0\n\ntrigger 0 x 1 1").err(), Some("3: invalid f32: 'x'".into()));
+    assert_eq!(parse_level("wall 0 0 -1 1").err(), Some("1: width must be positive".into()));
+    assert_eq!(parse_level("spawn 0 0 0").err(), Some("1: unexpected value: '0'".into()));
+}
@@ -24,9 +24,21 @@ mod api;
 mod resources;
 mod update_systems;
 mod draw_systems;
-mod components;
+#[macro_use] mod components;
 mod colors;
 mod notifications;
+mod monsters;
+mod weapons;
+mod health;
+mod projectiles;
+mod explosions;
+mod doors;
+mod director;
+mod snapshot;
+mod netplay;
+mod save;
+mod level;
+mod maze;
 
 use glium::glutin;
 use rustyline::Editor;
@@ -37,7 +49,7 @@ use std::str::FromStr;
 use std::path::Path;
 use std::time::Duration;
 use std::thread;
-use std::sync::mpsc::{channel, TryRecvError};
+use std::sync::mpsc::{channel, Receiver};
 use std::sync::Mutex;
 use std::sync::Arc;
 use std::fs::File;
@@ -45,6 +57,7 @@ use std::io::{self, Write};
 
 pub use api::Caller;
 pub use api::Callee;
+use api::CallerMsg;
 
 const BILLION: u64 = 1_000_000_000;
 
@@ -90,6 +103,110 @@ impl ::rustyline::completion::Completer for MyCompleter {
     }
 }
 
+fn create_window(matches: &clap::ArgMatches) -> glium::backend::glutin_backend::GlutinFacade {
+    use glium::DisplayBuild;
+
+    let mut builder = glutin::WindowBuilder::new().with_title("Ruga");
+
+    if matches.is_present("vsync") {
+        builder = builder.with_vsync();
+    }
+
+    builder = match matches.value_of("multisampling") {
+        Some("2") => builder.with_multisampling(2),
+        Some("4") => builder.with_multisampling(4),
+        Some("8") => builder.with_multisampling(8),
+        Some("16") => builder.with_multisampling(16),
+        Some(_) => unreachable!(),
+        None => builder,
+    };
+
+    builder = if let Some(mut dimensions) = matches.values_of("dimension") {
+        let width = u32::from_str(dimensions.next().unwrap()).unwrap();
+        let height = u32::from_str(dimensions.next().unwrap()).unwrap();
+        builder.with_dimensions(width, height)
+    } else {
+        builder.with_fullscreen(glutin::get_primary_monitor())
+    };
+
+    builder.build_glium().unwrap()
+}
+
+/// Run the update loop as fast as possible without window nor graphics
+///
+/// stop after `ticks` updates if some or when lua call `quit()`
+fn headless_loop(app: &mut app::App,
+                 lua: &Mutex<hlua::Lua>,
+                 api_rx: &Receiver<CallerMsg>,
+                 netplay: &mut Option<netplay::Netplay>,
+                 dt: f32,
+                 ticks: Option<u64>) {
+    let mut tick = 0;
+    loop {
+        if ticks.map(|ticks| tick >= ticks).unwrap_or(false) {
+            break;
+        }
+        if !update(app, lua, api_rx, netplay, dt) {
+            // waiting for the remote peer
+            thread::sleep(Duration::from_millis(1));
+            continue;
+        }
+        if app.must_quit() {
+            break;
+        }
+        tick += 1;
+    }
+}
+
+/// Execute lua update, api calls and update the app,
+/// return false if netplay is waiting for the remote peer
+fn update(app: &mut app::App,
+          lua: &Mutex<hlua::Lua>,
+          api_rx: &Receiver<CallerMsg>,
+          netplay: &mut Option<netplay::Netplay>,
+          dt: f32) -> bool {
+    if let Some(ref mut netplay) = *netplay {
+        if !netplay.update(app, lua, api_rx, dt) {
+            return false;
+        }
+    } else {
+        lua.lock()
+            .unwrap()
+            .execute::<()>(&*format!("update({})", dt))
+            .unwrap();
+        while let Ok(msg) = api_rx.try_recv() {
+            app.call(msg);
+        }
+        if app.must_quit() {
+            return true;
+        }
+        app.update(dt);
+    }
+    execute_lua_callbacks(app, lua);
+    true
+}
+
+/// Call lua input, through netplay if any
+fn input(lua: &Mutex<hlua::Lua>,
+         netplay: &mut Option<netplay::Netplay>,
+         state: String,
+         scancode: u32,
+         virtualcode: String) {
+    if let Some(ref mut netplay) = *netplay {
+        netplay.input(state, scancode, virtualcode);
+    } else {
+        let command = format!("input(\"{}\",{},\"{}\")", state, scancode, virtualcode);
+        lua.lock().unwrap().execute::<()>(&*command).unwrap();
+    }
+}
+
+fn execute_lua_callbacks(app: &mut app::App, lua: &Mutex<hlua::Lua>) {
+    let mut lua = lua.lock().unwrap();
+    for callback in app.lua_callbacks() {
+        lua.execute::<()>(&*callback).unwrap();
+    }
+}
+
 fn main() {
     let matches = clap::App::new("ruga")
         .version("0.3")
@@ -112,6 +229,19 @@ fn main() {
                  }
              })
              .takes_value(true))
+        .arg(clap::Arg::with_name("level")
+             .short("l")
+             .long("level")
+             .value_name("FILE")
+             .help("Load level file after configuration")
+             .validator(|s| {
+                 if Path::new(&*s).exists() {
+                     Ok(())
+                 } else {
+                     Err(format!("level file '{}' doesn't exist", s))
+                 }
+             })
+             .takes_value(true))
         .arg(clap::Arg::with_name("terminal")
              .short("t")
              .long("terminal")
@@ -147,38 +277,43 @@ fn main() {
              .possible_values(&["2", "4", "8", "16"])
              .help("Set multisampling")
              .takes_value(true))
+        .arg(clap::Arg::with_name("headless")
+             .long("headless")
+             .help("Run the simulation without window"))
+        .arg(clap::Arg::with_name("ticks")
+             .long("ticks")
+             .value_name("INT")
+             .requires("headless")
+             .validator(|s| {
+                 u64::from_str(&*s)
+                     .map(|_| ())
+                     .map_err(|e| format!("'{}' ticks is invalid : {}", s, e))
+             })
+             .help("Stop headless simulation after this number of ticks")
+             .takes_value(true))
+        .arg(clap::Arg::with_name("netplay")
+             .long("netplay")
+             .value_name("LOCAL> <REMOTE")
+             .help("Play with a remote peer: bind LOCAL udp address and send to REMOTE")
+             .number_of_values(2)
+             .takes_value(true))
+        .arg(clap::Arg::with_name("player")
+             .long("player")
+             .value_name("INT")
+             .possible_values(&["0", "1"])
+             .default_value("0")
+             .help("Set the player controlled on this peer in netplay")
+             .takes_value(true))
         .get_matches();
 
-    let window = {
-        use glium::DisplayBuild;
-
-        let mut builder = glutin::WindowBuilder::new().with_title("Ruga");
-
-        if matches.is_present("vsync") {
-            builder = builder.with_vsync();
-        }
-
-        builder = match matches.value_of("multisampling") {
-            Some("2") => builder.with_multisampling(2),
-            Some("4") => builder.with_multisampling(4),
-            Some("8") => builder.with_multisampling(8),
-            Some("16") => builder.with_multisampling(16),
-            Some(_) => unreachable!(),
-            None => builder,
-        };
-
-        builder = if let Some(mut dimensions) = matches.values_of("dimension") {
-            let width = u32::from_str(dimensions.next().unwrap()).unwrap();
-            let height = u32::from_str(dimensions.next().unwrap()).unwrap();
-            builder.with_dimensions(width, height)
-        } else {
-            builder.with_fullscreen(glutin::get_primary_monitor())
-        };
-
-        builder.build_glium().unwrap()
-    };
-    window.get_window().unwrap().set_cursor_state(glutin::CursorState::Grab).unwrap();
-    window.get_window().unwrap().set_cursor(glutin::MouseCursor::NoneCursor);
+    let player = u32::from_str(matches.value_of("player").unwrap()).unwrap();
+    let mut netplay = matches.values_of("netplay").map(|mut addresses| {
+        let local = addresses.next().unwrap();
+        let remote = addresses.next().unwrap();
+        netplay::Netplay::new(local, remote, player)
+            .unwrap_or_else(|e| panic!("netplay: can't bind '{}': {}", local, e))
+    });
+    let players = if netplay.is_some() { 2 } else { 1 };
 
     let (api_tx, api_rx) = channel();
 
@@ -186,10 +321,17 @@ fn main() {
     lua.openlibs();
     api::set_lua_caller(&mut lua, api_tx.clone());
     api::set_lua_callee(&mut lua);
+    lua.execute::<()>(&*format!("player = {}\nplayers = {}", player, players)).unwrap();
 
     if let Some(file) = matches.value_of("config") {
         lua.execute_from_reader::<(), _>(File::open(file).unwrap()).unwrap();
     }
+    if let Some(file) = matches.value_of("level") {
+        api_tx.send(CallerMsg::load_level((), file.into())).unwrap();
+    }
+    if let Some(ref mut netplay) = netplay {
+        netplay.setup(&api_rx);
+    }
 
     let lua = Arc::new(Mutex::new(lua));
     let terminal = if matches.is_present("terminal") {
@@ -235,11 +377,34 @@ fn main() {
         None
     };
 
-    let mut app = app::App::new(&window);
     let fps = u64::from_str(matches.value_of("fps").unwrap()).unwrap();
     let dt_ns = BILLION / fps;
     let dt = 1.0 / fps as f32;
 
+    if matches.is_present("headless") {
+        let ticks = matches.value_of("ticks").map(|s| u64::from_str(s).unwrap());
+        let mut app = app::App::new_headless();
+        app.set_local_player(player);
+        app.set_active_player(player);
+        headless_loop(&mut app, &lua, &api_rx, &mut netplay, dt, ticks);
+        println!("{}", app.summary());
+        if let Some(terminal) = terminal {
+            print!("[simulation has ended]");
+            io::stdout().flush().unwrap();
+            terminal.join().unwrap();
+            print!("\n");
+        }
+        return;
+    }
+
+    let window = create_window(&matches);
+    window.get_window().unwrap().set_cursor_state(glutin::CursorState::Grab).unwrap();
+    window.get_window().unwrap().set_cursor(glutin::MouseCursor::NoneCursor);
+
+    let mut app = app::App::new(&window);
+    app.set_local_player(player);
+    app.set_active_player(player);
+
     // Game loop inspired by http://gameprogrammingpatterns.com/game-loop.html
     // and piston event loop
     //
@@ -257,7 +422,7 @@ fn main() {
                 MouseInput(state, button) => {
                     use glium::glutin::MouseButton::*;
 
-                    let state = format!("\"{:?}\"", state).to_lowercase();
+                    let state = format!("{:?}", state).to_lowercase();
                     let code: u32 = match button {
                         Left => 0 + 1 << 8,
                         Right => 1 + 1 << 8,
@@ -265,11 +430,10 @@ fn main() {
                         Other(c) => c as u32 + 1 << 9,
                     };
                     let virtualcode = match button {
-                        Left | Right | Middle => format!("\"mouse{:?}\"", button).to_lowercase(),
-                        Other(c) => format!("\"mouse{:x}\"", c),
+                        Left | Right | Middle => format!("mouse{:?}", button).to_lowercase(),
+                        Other(c) => format!("mouse{:x}", c),
                     };
-                    let command = format!("input({},{},{})", state, code, virtualcode);
-                    lua.lock().unwrap().execute::<()>(&*command).unwrap();
+                    input(&lua, &mut netplay, state, code, virtualcode);
                 }
                 MouseMoved(x, y) => {
                     let (w, h) = window.get_window().unwrap().get_inner_size_pixels().unwrap();
@@ -296,13 +460,12 @@ fn main() {
                         }),
                     }
 
-                    let state = format!("\"{:?}\"", state).to_lowercase();
+                    let state = format!("{:?}", state).to_lowercase();
                     let virtualcode = match virtualcode {
-                        Some(c) => format!("\"{:?}\"", c).to_lowercase(),
-                        None => "\"none\"".into(),
+                        Some(c) => format!("{:?}", c).to_lowercase(),
+                        None => "none".into(),
                     };
-                    let command = format!("input({},{},{})", state, code, virtualcode);
-                    lua.lock().unwrap().execute::<()>(&*command).unwrap();
+                    input(&lua, &mut netplay, state, code as u32, virtualcode);
                 }
                 MouseWheel(delta, _) => {
                     use glium::glutin::MouseScrollDelta::*;
@@ -319,26 +482,12 @@ fn main() {
                 _ => (),
             }
         }
-        {
-            lua.lock()
-                .unwrap()
-                .execute::<()>(&*format!("update({})", dt))
-                .unwrap();
-        }
-        loop {
-            match api_rx.try_recv() {
-                Ok(msg) => app.call(msg),
-                Err(TryRecvError::Disconnected) => break,
-                Err(TryRecvError::Empty) => break,
-            }
-        }
+        // Update
+        update(&mut app, &lua, &api_rx, &mut netplay, dt);
         if app.must_quit() {
             break 'main_loop;
         }
 
-        // Update
-        app.update(dt);
-
         // Draw
         app.draw(window.draw());
 
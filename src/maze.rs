@@ -0,0 +1,177 @@
+//! Seeded maze generation
+//!
+//! The maze is a grid of cells separated by walls, its lower left corner is
+//! at the origin. It is carved by a randomized depth first search, then rooms
+//! of several cells are opened. The same parameters give the same maze.
+
+use utils::Rng;
+
+const ROOM_MIN_CELLS: u32 = 2;
+const ROOM_MAX_CELLS: u32 = 4;
+
+pub struct Maze {
+    /// rectangles: center x, center y, width, height
+    pub walls: Vec<[f32; 4]>,
+    /// center of rooms or dead ends if there is no room
+    pub spawn_points: Vec<[f32; 2]>,
+}
+
+/// Tiles of the maze: cells are at odd coordinates, walls in between
+struct Tiles {
+    width: usize,
+    height: usize,
+    open: Vec<bool>,
+}
+
+impl Tiles {
+    fn is_open(&self, x: usize, y: usize) -> bool {
+        self.open[y * self.width + x]
+    }
+    fn set_open(&mut self, x: usize, y: usize) {
+        self.open[y * self.width + x] = true;
+    }
+}
+
+/// Position of the start of the tile and its size along one axis
+fn tile_extent(index: usize, corridor_width: f32, wall_thickness: f32) -> (f32, f32) {
+    let walls = ((index + 1) / 2) as f32;
+    let cells = (index / 2) as f32;
+    let size = if index % 2 == 0 { wall_thickness } else { corridor_width };
+    (walls * wall_thickness + cells * corridor_width, size)
+}
+
+/// Error if corridor_width or wall_thickness isn't strictly positive
+pub fn generate(seed: u32, width: u32, height: u32, corridor_width: f32, wall_thickness: f32, rooms: u32) -> Result<Maze, String> {
+    if !(corridor_width > 0. && corridor_width.is_finite()) {
+        return Err(format!("invalid corridor width {}", corridor_width));
+    }
+    if !(wall_thickness > 0. && wall_thickness.is_finite()) {
+        return Err(format!("invalid wall thickness {}", wall_thickness));
+    }
+    let mut rng = Rng::new(seed);
+    let (width, height) = (width.max(1), height.max(1));
+    let mut tiles = Tiles {
+        width: 2 * width as usize + 1,
+        height: 2 * height as usize + 1,
+        open: vec![false; (2 * width as usize + 1) * (2 * height as usize + 1)],
+    };
+
+    // depth first search on cells
+    let start = (rng.below(width) as usize, rng.below(height) as usize);
+    let mut visited = vec![false; (width * height) as usize];
+    visited[start.1 * width as usize + start.0] = true;
+    tiles.set_open(2 * start.0 + 1, 2 * start.1 + 1);
+    let mut stack = vec!(start);
+    while let Some(&(x, y)) = stack.last() {
+        let mut neighbours = vec!();
+        if x > 0 { neighbours.push((x - 1, y)); }
+        if y > 0 { neighbours.push((x, y - 1)); }
+        if x + 1 < width as usize { neighbours.push((x + 1, y)); }
+        if y + 1 < height as usize { neighbours.push((x, y + 1)); }
+        neighbours.retain(|&(nx, ny)| !visited[ny * width as usize + nx]);
+
+        if neighbours.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let (nx, ny) = neighbours[rng.below(neighbours.len() as u32) as usize];
+        visited[ny * width as usize + nx] = true;
+        tiles.set_open(x + nx + 1, y + ny + 1);
+        tiles.set_open(2 * nx + 1, 2 * ny + 1);
+        stack.push((nx, ny));
+    }
+
+    let center = |x: usize, y: usize| {
+        let (x0, w) = tile_extent(x, corridor_width, wall_thickness);
+        let (y0, h) = tile_extent(y, corridor_width, wall_thickness);
+        [x0 + w / 2., y0 + h / 2.]
+    };
+
+    // rooms open all walls inside a rectangle of cells
+    let mut spawn_points = vec!();
+    for _ in 0..rooms {
+        let room_width = (ROOM_MIN_CELLS + rng.below(ROOM_MAX_CELLS - ROOM_MIN_CELLS + 1)).min(width);
+        let room_height = (ROOM_MIN_CELLS + rng.below(ROOM_MAX_CELLS - ROOM_MIN_CELLS + 1)).min(height);
+        let x = rng.below(width - room_width + 1) as usize;
+        let y = rng.below(height - room_height + 1) as usize;
+        let (x0, y0) = (2 * x + 1, 2 * y + 1);
+        let (x1, y1) = (2 * (x + room_width as usize), 2 * (y + room_height as usize));
+        for tile_x in x0..x1 {
+            for tile_y in y0..y1 {
+                tiles.set_open(tile_x, tile_y);
+            }
+        }
+        let (a, b) = (center(x0, y0), center(x1 - 1, y1 - 1));
+        spawn_points.push([(a[0] + b[0]) / 2., (a[1] + b[1]) / 2.]);
+    }
+    if rooms == 0 {
+        for x in 0..width as usize {
+            for y in 0..height as usize {
+                let (tile_x, tile_y) = (2 * x + 1, 2 * y + 1);
+                let openings = [(tile_x - 1, tile_y), (tile_x + 1, tile_y), (tile_x, tile_y - 1), (tile_x, tile_y + 1)]
+                    .iter()
+                    .filter(|&&(tx, ty)| tiles.is_open(tx, ty))
+                    .count();
+                if openings == 1 {
+                    spawn_points.push(center(tile_x, tile_y));
+                }
+            }
+        }
+    }
+
+    // merge closed tiles in horizontal runs then runs of same columns in rectangles
+    let mut rectangles: Vec<(usize, usize, usize, usize)> = vec!();
+    for y in 0..tiles.height {
+        let mut x = 0;
+        while x < tiles.width {
+            if tiles.is_open(x, y) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < tiles.width && !tiles.is_open(x, y) {
+                x += 1;
+            }
+            let extended = rectangles.iter_mut()
+                .find(|r| r.0 == start && r.1 == x && r.3 == y)
+                .map(|r| r.3 = y + 1)
+                .is_some();
+            if !extended {
+                rectangles.push((start, x, y, y + 1));
+            }
+        }
+    }
+
+    let walls = rectangles.iter().map(|&(x0, x1, y0, y1)| {
+        let (left, _) = tile_extent(x0, corridor_width, wall_thickness);
+        let (right, _) = tile_extent(x1, corridor_width, wall_thickness);
+        let (bottom, _) = tile_extent(y0, corridor_width, wall_thickness);
+        let (top, _) = tile_extent(y1, corridor_width, wall_thickness);
+        [(left + right) / 2., (bottom + top) / 2., right - left, top - bottom]
+    }).collect();
+
+    Ok(Maze {
+        walls: walls,
+        spawn_points: spawn_points,
+    })
+}
+
+#[test]
+fn seed_test() {
+    let maze = generate(3, 10, 8, 2., 0.5, 2).unwrap();
+    let same = generate(3, 10, 8, 2., 0.5, 2).unwrap();
+    assert_eq!(maze.walls, same.walls);
+    assert_eq!(maze.spawn_points, same.spawn_points);
+    assert_eq!(maze.spawn_points.len(), 2);
+
+    let other = generate(4, 10, 8, 2., 0.5, 2).unwrap();
+    assert!(other.walls != maze.walls);
+}
+
+#[test]
+fn invalid_size_test() {
+    assert!(generate(0, 4, 4, 0., 0.5, 0).is_err());
+    assert!(generate(0, 4, 4, 2., -1., 0).is_err());
+    assert!(generate(0, 4, 4, ::std::f32::NAN, 0.5, 0).is_err());
+    assert!(generate(0, 4, 4, 2., 0.5, 0).is_ok());
+}
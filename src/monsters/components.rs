@@ -0,0 +1,55 @@
+use super::*;
+
+impl_component! {
+    Zombie: HashMapStorage,
+    Spider: VecStorage,
+    SpiderSpawner: HashMapStorage,
+}
+
+#[derive(Clone)]
+pub struct Zombie {
+    pub state: ZombieState,
+    /// progress toward the next state in [0,1]:
+    /// increases while a hero is seen, faster if he is close, decreases otherwise
+    pub alert: f32,
+    pub walk_force: f32,
+    pub run_force: f32,
+    /// distance at which heroes are seen
+    pub vision: f32,
+    /// time to promote the state with a hero at contact
+    pub alert_time: f32,
+    /// time to demote the state without seeing any hero
+    pub decay_time: f32,
+}
+
+/// Follows corridors and splits at each intersection, see SpiderSystem
+#[derive(Clone)]
+pub struct Spider {
+    /// direction of the followed corridor
+    pub heading: f32,
+    pub velocity: f32,
+    /// distance at which walls are seen, a bit more than the corridor width
+    pub sight: f32,
+    /// remaining time to live
+    pub lifetime: Option<f32>,
+    /// no split when this number of spiders is alive
+    pub population: u32,
+    /// left and right sides were open at last update
+    pub openings: (bool, bool),
+}
+
+/// Creation point of spiders, they are emitted in turn in each open direction
+#[derive(Clone)]
+pub struct SpiderSpawner {
+    /// spiders per second
+    pub rate: f32,
+    pub timer: f32,
+    /// spiders left to emit
+    pub remaining: Option<u32>,
+    /// next direction in quarter turns
+    pub direction: u32,
+    pub velocity: f32,
+    pub sight: f32,
+    pub lifetime: Option<f32>,
+    pub population: u32,
+}
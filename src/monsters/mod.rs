@@ -0,0 +1,28 @@
+pub mod components;
+pub mod update_systems;
+
+/// States of the zombie from the least to the most aggressive
+#[derive(Clone, Copy, PartialEq)]
+pub enum ZombieState {
+    /// doesn't move
+    Idle,
+    /// walks toward the hero without path finding
+    Walk,
+    /// runs toward the hero without path finding
+    Run,
+}
+
+impl ZombieState {
+    pub fn promote(self) -> ZombieState {
+        match self {
+            ZombieState::Idle => ZombieState::Walk,
+            ZombieState::Walk | ZombieState::Run => ZombieState::Run,
+        }
+    }
+    pub fn demote(self) -> ZombieState {
+        match self {
+            ZombieState::Idle | ZombieState::Walk => ZombieState::Idle,
+            ZombieState::Run => ZombieState::Walk,
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use specs::{self, Join};
+use fnv::FnvHashSet;
+use components::*;
+use resources::{CollisionEvents, EntitySpawns, PhysicWorld};
+use physics::{RayCast, ContinueOrStop, ContactState};
+use entities::{EntityBuilderMsg, MOVE_FORCE, WALL_GROUP};
+use super::*;
+
+/// Steering toward the middle of the corridor
+const SPIDER_CENTERING: f32 = 2.;
+
+pub struct ZombieSystem;
+impl specs::System<::utils::UpdateContext> for ZombieSystem {
+    fn run(&mut self, arg: specs::RunArg, context: ::utils::UpdateContext) {
+        let (mut zombies, mut forces, states, player_controls, physic_world) = arg.fetch(|world| {
+            (
+                world.write::<Zombie>(),
+                world.write::<PhysicForces>(),
+                world.read::<PhysicState>(),
+                world.read::<PlayerControl>(),
+                world.read_resource::<PhysicWorld>(),
+            )
+        });
+
+        let heroes = (&player_controls, &states).iter()
+            .map(|(_, state)| state.pos)
+            .collect::<Vec<_>>();
+
+        for (zombie, forces, state) in (&mut zombies, &mut forces, &states).iter() {
+            let pos = state.pos;
+
+            // closest hero in sight
+            let mut target: Option<(f32, f32)> = None;
+            for hero in &heroes {
+                let (dx, dy) = (hero[0] - pos[0], hero[1] - pos[1]);
+                let distance = (dx.powi(2) + dy.powi(2)).sqrt();
+                if distance > zombie.vision || target.map_or(false, |(d, _)| d <= distance) {
+                    continue;
+                }
+                let ray = RayCast {
+                    origin: pos,
+                    angle: dy.atan2(dx),
+                    length: distance,
+                    mask: WALL_GROUP,
+                    group: !0,
+                    not: vec!(),
+                };
+                let mut visible = true;
+                physic_world.raycast(&ray, &mut |_| {
+                    visible = false;
+                    ContinueOrStop::Stop
+                });
+                if visible {
+                    target = Some((distance, ray.angle));
+                }
+            }
+
+            match target {
+                Some((distance, _)) => {
+                    let closeness = 1. - distance / zombie.vision;
+                    zombie.alert += context.dt * closeness / zombie.alert_time;
+                    if zombie.alert >= 1. {
+                        if zombie.state == ZombieState::Run {
+                            zombie.alert = 1.;
+                        } else {
+                            zombie.state = zombie.state.promote();
+                            zombie.alert = 0.;
+                        }
+                    }
+                }
+                None => {
+                    zombie.alert -= context.dt / zombie.decay_time;
+                    if zombie.alert <= 0. {
+                        if zombie.state == ZombieState::Idle {
+                            zombie.alert = 0.;
+                        } else {
+                            zombie.state = zombie.state.demote();
+                            zombie.alert = 1.;
+                        }
+                    }
+                }
+            }
+
+            // without a target the zombie keeps its direction
+            let force = forces.named(MOVE_FORCE);
+            if let Some((_, angle)) = target {
+                force.angle = angle;
+            }
+            match zombie.state {
+                ZombieState::Idle => force.strength = 0.,
+                ZombieState::Walk => {
+                    force.strength = 1.;
+                    force.coef = zombie.walk_force;
+                }
+                ZombieState::Run => {
+                    force.strength = 1.;
+                    force.coef = zombie.run_force;
+                }
+            }
+        }
+    }
+}
+
+/// Corridor distance along a ray, None if nothing is seen
+fn probe(physic_world: &PhysicWorld, origin: [f32; 2], angle: f32, length: f32) -> Option<f32> {
+    let ray = RayCast {
+        origin: origin,
+        angle: angle,
+        length: length,
+        mask: WALL_GROUP,
+        group: !0,
+        not: vec!(),
+    };
+    let mut distance = None;
+    physic_world.raycast(&ray, &mut |(_, min, _)| {
+        distance = Some(min.max(0.));
+        ContinueOrStop::Stop
+    });
+    distance
+}
+
+/// New spider with the same parameters
+fn split(spider: &Spider, pos: [f32; 2], angle: f32) -> EntityBuilderMsg {
+    EntityBuilderMsg::add_spider((), pos[0], pos[1], angle, spider.velocity, spider.sight, spider.lifetime.unwrap_or(0.), spider.population)
+}
+
+/// Spiders go forward in their corridor and steer to stay in its middle.
+///
+/// A side is open when nothing is seen there at sight distance, both from
+/// the spider and from a bit behind it. When a side opens a new spider is
+/// spawned in it. When the wall in front is closer than half the sight the
+/// spider turns to an open side, splitting if both are open.
+///
+/// Spiders die at dead ends, on contact with a hero and when their lifetime is over.
+pub struct SpiderSystem;
+impl specs::System<::utils::UpdateContext> for SpiderSystem {
+    fn run(&mut self, arg: specs::RunArg, context: ::utils::UpdateContext) {
+        use std::f32::consts::FRAC_PI_2;
+
+        let (mut spiders, mut spawners, mut forces, states, player_controls, physic_world, collision_events, mut spawns, entities) = arg.fetch(|world| {
+            (
+                world.write::<Spider>(),
+                world.write::<SpiderSpawner>(),
+                world.write::<PhysicForces>(),
+                world.read::<PhysicState>(),
+                world.read::<PlayerControl>(),
+                world.read_resource::<PhysicWorld>(),
+                world.read_resource::<CollisionEvents>(),
+                world.write_resource::<EntitySpawns>(),
+                world.entities(),
+            )
+        });
+
+        let mut population = (&spiders).iter().count() as u32;
+
+        for (spawner, state) in (&mut spawners, &states).iter() {
+            spawner.timer += context.dt * spawner.rate;
+            while spawner.timer >= 1. && spawner.remaining != Some(0) && population < spawner.population {
+                spawner.timer -= 1.;
+                // next direction not blocked by a wall
+                let angle = (0..4)
+                    .map(|i| (spawner.direction + i) % 4)
+                    .find(|&direction| probe(&physic_world, state.pos, direction as f32 * FRAC_PI_2, spawner.sight / 2.).is_none())
+                    .map(|direction| {
+                        spawner.direction = direction + 1;
+                        direction as f32 * FRAC_PI_2
+                    });
+                if let Some(angle) = angle {
+                    spawns.0.push(EntityBuilderMsg::add_spider((), state.pos[0], state.pos[1], angle, spawner.velocity, spawner.sight, spawner.lifetime.unwrap_or(0.), spawner.population));
+                    spawner.remaining = spawner.remaining.map(|remaining| remaining - 1);
+                    population += 1;
+                }
+            }
+            spawner.timer = spawner.timer.min(1.);
+        }
+
+        let mut touched = FnvHashSet::default();
+        for event in &collision_events.events {
+            if event.state == ContactState::Ended || event.sensor {
+                continue;
+            }
+            if player_controls.get(event.a).is_some() {
+                touched.insert(event.b);
+            }
+            if player_controls.get(event.b).is_some() {
+                touched.insert(event.a);
+            }
+        }
+
+        for (spider, forces, state, entity) in (&mut spiders, &mut forces, &states, &entities).iter() {
+            if let Some(ref mut lifetime) = spider.lifetime {
+                *lifetime -= context.dt;
+            }
+            if spider.lifetime.map_or(false, |lifetime| lifetime <= 0.) || touched.contains(&entity) {
+                arg.delete(entity);
+                population -= 1;
+                continue;
+            }
+
+            let pos = state.pos;
+            let behind = [pos[0] - spider.sight / 4. * spider.heading.cos(), pos[1] - spider.sight / 4. * spider.heading.sin()];
+            let side = |angle: f32| {
+                let distance = probe(&physic_world, pos, angle, spider.sight);
+                let open = distance.is_none() && probe(&physic_world, behind, angle, spider.sight).is_none();
+                (open, distance)
+            };
+            let (left, left_distance) = side(spider.heading + FRAC_PI_2);
+            let (right, right_distance) = side(spider.heading - FRAC_PI_2);
+            let blocked = probe(&physic_world, pos, spider.heading, spider.sight / 2.).is_some();
+
+            if blocked {
+                match (left, right) {
+                    (false, false) => {
+                        arg.delete(entity);
+                        population -= 1;
+                        continue;
+                    }
+                    (true, false) => spider.heading += FRAC_PI_2,
+                    (false, true) => spider.heading -= FRAC_PI_2,
+                    (true, true) => {
+                        if population < spider.population {
+                            spawns.0.push(split(spider, pos, spider.heading - FRAC_PI_2));
+                            population += 1;
+                        }
+                        spider.heading += FRAC_PI_2;
+                    }
+                }
+                // the corridor it comes from is now on a side
+                spider.openings = (true, true);
+            } else {
+                if left && !spider.openings.0 && population < spider.population {
+                    spawns.0.push(split(spider, pos, spider.heading + FRAC_PI_2));
+                    population += 1;
+                }
+                if right && !spider.openings.1 && population < spider.population {
+                    spawns.0.push(split(spider, pos, spider.heading - FRAC_PI_2));
+                    population += 1;
+                }
+                spider.openings = (left, right);
+            }
+            spider.heading = ::utils::minus_pi_pi(spider.heading);
+
+            // steer toward the middle of the corridor
+            let offset = match (left_distance, right_distance) {
+                (Some(left), Some(right)) if !blocked => (left - right) / 2.,
+                _ => 0.,
+            };
+            forces.named(MOVE_FORCE).angle = spider.heading + (SPIDER_CENTERING * offset / spider.sight).atan();
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_planner<S: 'static + specs::System<::utils::UpdateContext>>(system: S) -> specs::Planner<::utils::UpdateContext> {
+    let mut world = specs::World::new();
+    ::resources::add_resources(&mut world);
+    register_components(&mut world);
+    let mut planner = specs::Planner::new(world, 1);
+    planner.add_system(system, "system", 0);
+    planner
+}
+
+#[cfg(test)]
+fn add_test_wall(world: &mut specs::World, pos: [f32; 2], width: f32, height: f32) {
+    use physics::{EntityInformation, Shape};
+
+    let entity = world.create_now().build();
+    world.write_resource::<PhysicWorld>().insert_static(EntityInformation {
+        entity: entity,
+        pos: pos,
+        group: WALL_GROUP,
+        mask: !0,
+        shape: Shape::Rectangle(width, height),
+    });
+}
+
+#[test]
+fn zombie_sight_test() {
+    let mut planner = test_planner(ZombieSystem);
+    let zombie = {
+        let world = planner.mut_world();
+        world.create_now()
+            .with(PhysicState::new([5., 0.]))
+            .with(PlayerControl(0))
+            .build();
+        world.create_now()
+            .with(PhysicState::new([0., 0.]))
+            .with(PhysicForces::new())
+            .with(Zombie {
+                state: ZombieState::Idle,
+                alert: 0.,
+                walk_force: 1.,
+                run_force: 2.,
+                vision: 10.,
+                alert_time: 1.,
+                decay_time: 1.,
+            })
+            .build()
+    };
+    let mut update = |ticks| {
+        for _ in 0..ticks {
+            planner.dispatch(::utils::UpdateContext { dt: 0.5 });
+            planner.wait();
+        }
+        let world = planner.mut_world();
+        let state = world.read::<Zombie>().get(zombie).unwrap().state;
+        let force = world.write::<PhysicForces>().get_mut(zombie).unwrap().named(MOVE_FORCE).clone();
+        (state, force.strength * force.coef)
+    };
+
+    // the hero at half the vision is seen: a quarter of alert by update
+    assert!(update(3) == (ZombieState::Idle, 0.));
+    assert!(update(1) == (ZombieState::Walk, 1.));
+    assert!(update(4) == (ZombieState::Run, 2.));
+    assert!(update(4) == (ZombieState::Run, 2.));
+
+    // hidden by a wall: half of alert lost by update
+    add_test_wall(planner.mut_world(), [2.5, 0.], 0.2, 4.);
+    let mut update = |ticks| {
+        for _ in 0..ticks {
+            planner.dispatch(::utils::UpdateContext { dt: 0.5 });
+            planner.wait();
+        }
+        planner.mut_world().read::<Zombie>().get(zombie).unwrap().state
+    };
+    assert!(update(1) == ZombieState::Run);
+    assert!(update(1) == ZombieState::Walk);
+    assert!(update(2) == ZombieState::Idle);
+    assert!(update(4) == ZombieState::Idle);
+}
+
+/// Spawns and heading of a spider at the origin heading right after an update,
+/// None if it died
+#[cfg(test)]
+fn spider_update(walls: &[([f32; 2], f32, f32)], population: u32, openings: (bool, bool)) -> (usize, Option<f32>) {
+    let mut planner = test_planner(SpiderSystem);
+    let spider = {
+        let world = planner.mut_world();
+        for &(pos, width, height) in walls {
+            add_test_wall(world, pos, width, height);
+        }
+        world.create_now()
+            .with(PhysicState::new([0., 0.]))
+            .with(PhysicForces::new())
+            .with(Spider {
+                heading: 0.,
+                velocity: 1.,
+                sight: 4.,
+                lifetime: None,
+                population: population,
+                openings: openings,
+            })
+            .build()
+    };
+    planner.dispatch(::utils::UpdateContext { dt: 0.1 });
+    planner.wait();
+    let world = planner.mut_world();
+    world.maintain();
+    let heading = world.read::<Spider>().get(spider).map(|spider| spider.heading);
+    let spawns = world.read_resource::<EntitySpawns>().0.len();
+    (spawns, heading)
+}
+
+#[test]
+fn spider_test() {
+    use std::f32::consts::FRAC_PI_2;
+
+    let left = ([0., 1.5], 20., 0.2);
+    let right = ([0., -1.5], 20., 0.2);
+    let front = ([1.5, 0.], 0.2, 20.);
+
+    // dead end
+    assert_eq!(spider_update(&[left, right, front], 10, (false, false)), (0, None));
+    // both sides open in front of a wall: splits and turns left
+    assert_eq!(spider_update(&[front], 10, (false, false)), (1, Some(FRAC_PI_2)));
+    // no split when the population is reached
+    assert_eq!(spider_update(&[front], 1, (false, false)), (0, Some(FRAC_PI_2)));
+    // only the right side open in front of a wall: turns without split
+    assert_eq!(spider_update(&[left, front], 10, (false, false)), (0, Some(-FRAC_PI_2)));
+    // a side that opens splits once
+    assert_eq!(spider_update(&[right], 10, (false, false)), (1, Some(0.)));
+    assert_eq!(spider_update(&[right], 10, (true, false)), (0, Some(0.)));
+    // corridor
+    assert_eq!(spider_update(&[left, right], 10, (false, false)), (0, Some(0.)));
+}
@@ -0,0 +1,480 @@
+//! Rollback netplay between two peers over UDP
+//!
+//! Peers only exchange their input events. The world is snapshotted at each
+//! tick, the remote player is predicted to keep its last input and when a late
+//! remote input arrives the world is restored to its tick and re-simulated.
+//!
+//! Lua `input` is executed once per event with the global `player` set to the
+//! player of the event, at the tick of the event or on arrival for late remote
+//! events. The api calls made at a tick are recorded and applied again on
+//! re-simulation, Lua `input` and `update` are not executed again.
+//! Thus player api calls must be made in `input` to be synchronized.
+//! Callbacks are executed once: on re-simulation only the callbacks not
+//! delivered yet for the tick are executed.
+//!
+//! The api calls of the configuration, made before the first update, are
+//! recorded as setup calls of player 0 so they are applied first and in the
+//! same order on both peers.
+//!
+//! Lua globals are not rolled back: state derived from inputs must be kept in
+//! the world through api calls, not in Lua globals read by `update`.
+//!
+//! Peers on different platforms must enable physic quantization
+//! (`set_physic_quantum`) to stay in sync.
+//!
+//! Packets are lines of text:
+//!
+//! * `tick T`: the sender has sent all its inputs of ticks before T
+//! * `ack A`: the sender has received all inputs of ticks before A
+//! * `sync T C`: checksum of the sender world at tick T
+//! * `input T I STATE SCANCODE VIRTUALCODE`: the I-th input of tick T
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::UdpSocket;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::mpsc::Receiver;
+use std::io;
+
+use fnv::FnvHashSet;
+use hlua;
+use api::{Caller, CallerMsg};
+use app::App;
+use snapshot::Snapshot;
+
+/// Maximal number of ticks the simulation can run ahead of the remote peer
+const ROLLBACK_WINDOW: u64 = 8;
+const MAX_PACKET_SIZE: usize = 65507;
+/// Number of checksums of past ticks kept to detect desync
+const CHECKSUM_HISTORY: usize = 64;
+/// Player active during the api calls of the configuration
+const SETUP_PLAYER: u32 = 0;
+
+#[derive(Clone)]
+struct Input {
+    tick: u64,
+    index: usize,
+    state: String,
+    scancode: u32,
+    virtualcode: String,
+}
+
+impl Input {
+    fn to_line(&self) -> String {
+        format!("input {} {} {} {} {}", self.tick, self.index, self.state, self.scancode, self.virtualcode)
+    }
+    /// Parse the words following `input`, the state and virtual code are
+    /// checked as they come from the remote peer
+    fn from_words(words: &[&str]) -> Option<Input> {
+        if words.len() != 5 {
+            return None;
+        }
+        if words[2] != "pressed" && words[2] != "released" {
+            return None;
+        }
+        if words[4].is_empty() || !words[4].chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+            return None;
+        }
+        match (u64::from_str(words[0]), usize::from_str(words[1]), u32::from_str(words[3])) {
+            (Ok(tick), Ok(index), Ok(scancode)) => Some(Input {
+                tick: tick,
+                index: index,
+                state: words[2].into(),
+                scancode: scancode,
+                virtualcode: words[4].into(),
+            }),
+            _ => None,
+        }
+    }
+    /// Call Lua `input` with the global `player` set
+    fn execute(&self, lua: &Mutex<hlua::Lua>, player: u32) {
+        let mut lua = lua.lock().unwrap();
+        lua.set("player", player);
+        let mut input: hlua::LuaFunction<_> = lua.get("input").expect("lua input function is missing");
+        input.call_with_args::<(), _, _>((&*self.state, self.scancode, &*self.virtualcode)).unwrap();
+    }
+}
+
+pub struct Netplay {
+    socket: UdpSocket,
+    local_player: u32,
+    remote_player: u32,
+    /// next tick to simulate
+    tick: u64,
+    /// all remote inputs of ticks before it have been received
+    remote_tick: u64,
+    /// the remote peer has received all local inputs of ticks before it
+    ack: u64,
+    /// local inputs of the current tick not executed yet
+    pending: Vec<Input>,
+    /// local inputs not acknowledged
+    local_inputs: Vec<Input>,
+    /// remote inputs received of ticks after remote_tick
+    received: FnvHashSet<(u64, usize)>,
+    /// remote inputs received ahead of the simulation, executed at their tick
+    remote_pending: BTreeMap<(u64, usize), Input>,
+    /// api calls made at each tick and the player active during the call
+    calls: BTreeMap<u64, Vec<(u32, CallerMsg)>>,
+    /// Lua callbacks executed for each tick since the first snapshot
+    delivered: BTreeMap<u64, Vec<String>>,
+    /// snapshots of the world at the beginning of each tick since remote_tick
+    snapshots: VecDeque<(u64, Snapshot)>,
+    /// checksums of ticks that can't be rolled back anymore
+    checksums: VecDeque<(u64, u64)>,
+    remote_sync: Option<(u64, u64)>,
+    desync: bool,
+}
+
+impl Netplay {
+    /// Bind local address and exchange with remote address,
+    /// the remote peer is the other player of the two
+    pub fn new(local: &str, remote: &str, local_player: u32) -> io::Result<Netplay> {
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(remote)?;
+        socket.set_nonblocking(true)?;
+        Ok(Netplay {
+            socket: socket,
+            local_player: local_player,
+            remote_player: 1 - local_player,
+            tick: 0,
+            remote_tick: 0,
+            ack: 0,
+            pending: vec!(),
+            local_inputs: vec!(),
+            received: FnvHashSet::default(),
+            remote_pending: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            delivered: BTreeMap::new(),
+            snapshots: VecDeque::new(),
+            checksums: VecDeque::new(),
+            remote_sync: None,
+            desync: false,
+        })
+    }
+    /// Record the api calls made by the configuration,
+    /// must be called before the first update
+    pub fn setup(&mut self, api_rx: &Receiver<CallerMsg>) {
+        self.record(api_rx, 0, SETUP_PLAYER);
+    }
+    /// Local input event, executed at next update
+    pub fn input(&mut self, state: String, scancode: u32, virtualcode: String) {
+        let index = self.local_inputs.iter()
+            .chain(self.pending.iter())
+            .filter(|input| input.tick == self.tick)
+            .count();
+        self.pending.push(Input {
+            tick: self.tick,
+            index: index,
+            state: state,
+            scancode: scancode,
+            virtualcode: virtualcode,
+        });
+    }
+    /// Update the app by one tick unless the remote peer is too late,
+    /// return whether the app has been updated
+    pub fn update(&mut self, app: &mut App, lua: &Mutex<hlua::Lua>, api_rx: &Receiver<CallerMsg>, dt: f32) -> bool {
+        let (tick, player) = (self.tick, self.local_player);
+        self.record(api_rx, tick, player);
+
+        if let Some(late_tick) = self.receive(lua, api_rx) {
+            self.rollback(app, lua, late_tick, dt);
+        }
+        self.check_sync();
+
+        if self.tick >= self.remote_tick + ROLLBACK_WINDOW {
+            self.send();
+            return false;
+        }
+
+        let later = self.remote_pending.split_off(&(tick + 1, 0));
+        let remote_inputs = ::std::mem::replace(&mut self.remote_pending, later);
+        self.execute_remote(remote_inputs.into_iter().map(|(_, input)| input), lua, api_rx);
+
+        for input in ::std::mem::replace(&mut self.pending, vec!()) {
+            input.execute(lua, player);
+            self.record(api_rx, tick, player);
+            self.local_inputs.push(input);
+        }
+        lua.lock()
+            .unwrap()
+            .execute::<()>(&*format!("update({})", dt))
+            .unwrap();
+        self.record(api_rx, tick, player);
+
+        self.snapshots.push_back((tick, app.snapshot()));
+        self.apply(app, tick);
+        app.update(dt);
+        let callbacks = app.lua_callbacks();
+        self.deliver(lua, tick, callbacks);
+        self.tick += 1;
+
+        self.prune();
+        self.send();
+        true
+    }
+    /// Execute remote inputs and record their api calls at their tick
+    fn execute_remote<I: Iterator<Item=Input>>(&mut self, inputs: I, lua: &Mutex<hlua::Lua>, api_rx: &Receiver<CallerMsg>) {
+        let player = self.remote_player;
+        let mut executed = false;
+        for input in inputs {
+            input.execute(lua, player);
+            self.record(api_rx, input.tick, player);
+            executed = true;
+        }
+        if executed {
+            lua.lock().unwrap().set("player", self.local_player);
+        }
+    }
+    fn record(&mut self, api_rx: &Receiver<CallerMsg>, tick: u64, player: u32) {
+        while let Ok(msg) = api_rx.try_recv() {
+            self.calls.entry(tick).or_insert(vec!()).push((player, msg));
+        }
+    }
+    /// Apply the calls of the tick, ordered by player to be the same on both peers
+    fn apply(&self, app: &mut App, tick: u64) {
+        if let Some(calls) = self.calls.get(&tick) {
+            let mut calls = calls.iter().collect::<Vec<_>>();
+            calls.sort_by_key(|&&(player, _)| player);
+            for &&(player, ref msg) in &calls {
+                app.set_active_player(player);
+                app.call(msg.clone());
+            }
+        }
+        app.set_active_player(self.local_player);
+    }
+    /// Execute the callbacks of the tick that haven't been executed yet
+    fn deliver(&mut self, lua: &Mutex<hlua::Lua>, tick: u64, callbacks: Vec<String>) {
+        let delivered = self.delivered.entry(tick).or_insert(vec!());
+        let mut already = delivered.clone();
+        let mut lua = lua.lock().unwrap();
+        for callback in callbacks {
+            match already.iter().position(|c| *c == callback) {
+                Some(index) => { already.swap_remove(index); },
+                None => {
+                    lua.execute::<()>(&*callback).unwrap();
+                    delivered.push(callback);
+                },
+            }
+        }
+    }
+    /// Restore the world at tick and simulate again until current tick
+    fn rollback(&mut self, app: &mut App, lua: &Mutex<hlua::Lua>, tick: u64, dt: f32) {
+        let first = self.snapshots.front().expect("no snapshot to rollback").0;
+        let start = (tick - first) as usize;
+        app.restore(&self.snapshots[start].1);
+        for i in start..self.snapshots.len() {
+            let tick = self.snapshots[i].0;
+            if i != start {
+                self.snapshots[i].1 = app.snapshot();
+            }
+            self.apply(app, tick);
+            app.update(dt);
+            let callbacks = app.lua_callbacks();
+            self.deliver(lua, tick, callbacks);
+        }
+    }
+    /// Execute new late remote inputs, queue the others,
+    /// and return the first tick to simulate again if any
+    fn receive(&mut self, lua: &Mutex<hlua::Lua>, api_rx: &Receiver<CallerMsg>) -> Option<u64> {
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let mut late = BTreeMap::new();
+        loop {
+            let len = match self.socket.recv(&mut buf) {
+                Ok(len) => len,
+                // would block or the remote peer isn't started yet
+                Err(_) => break,
+            };
+            let packet = String::from_utf8_lossy(&buf[..len]).into_owned();
+            let mut packet_tick = 0;
+            for line in packet.lines() {
+                let words = line.split_whitespace().collect::<Vec<_>>();
+                match (words.first(), words.len()) {
+                    (Some(&"tick"), 2) => packet_tick = u64::from_str(words[1]).unwrap_or(0),
+                    (Some(&"ack"), 2) => {
+                        let ack = u64::from_str(words[1]).unwrap_or(0);
+                        self.ack = self.ack.max(ack);
+                    },
+                    (Some(&"sync"), 3) => {
+                        if let (Ok(tick), Ok(checksum)) = (u64::from_str(words[1]), u64::from_str_radix(words[2], 16)) {
+                            self.remote_sync = Some((tick, checksum));
+                        }
+                    },
+                    (Some(&"input"), _) => match Input::from_words(&words[1..]) {
+                        Some(input) => {
+                            let key = (input.tick, input.index);
+                            if input.tick < self.remote_tick || self.received.contains(&key) {
+                                continue;
+                            }
+                            self.received.insert(key);
+                            if input.tick < self.tick {
+                                late.insert(key, input);
+                            } else {
+                                self.remote_pending.insert(key, input);
+                            }
+                        },
+                        None => println!("netplay: invalid input: {}", line),
+                    },
+                    _ => println!("netplay: invalid line: {}", line),
+                }
+            }
+            self.remote_tick = self.remote_tick.max(packet_tick);
+        }
+        let late_tick = late.keys().next().map(|&(tick, _)| tick);
+        self.execute_remote(late.into_iter().map(|(_, input)| input), lua, api_rx);
+        let remote_tick = self.remote_tick;
+        self.received.retain(|&(tick, _)| tick >= remote_tick);
+        late_tick
+    }
+    /// Compare the checksum of the remote world with the local one
+    fn check_sync(&mut self) {
+        if let Some((tick, checksum)) = self.remote_sync {
+            if let Some(&(_, local)) = self.checksums.iter().find(|&&(t, _)| t == tick) {
+                if local != checksum && !self.desync {
+                    println!("netplay: desync at tick {}", tick);
+                    self.desync = true;
+                }
+                self.remote_sync = None;
+            } else if self.checksums.front().map_or(false, |&(t, _)| t > tick) {
+                self.remote_sync = None;
+            }
+        }
+    }
+    /// Forget what can't be rolled back anymore
+    fn prune(&mut self) {
+        while self.snapshots.len() > 1 && self.snapshots[0].0 < self.remote_tick {
+            let (tick, snapshot) = self.snapshots.pop_front().unwrap();
+            self.checksums.push_back((tick, snapshot.checksum));
+            if self.checksums.len() > CHECKSUM_HISTORY {
+                self.checksums.pop_front();
+            }
+        }
+        let first = self.snapshots[0].0;
+        self.calls = self.calls.split_off(&first);
+        self.delivered = self.delivered.split_off(&first);
+        let ack = self.ack;
+        self.local_inputs.retain(|input| input.tick >= ack);
+    }
+    fn send(&self) {
+        let mut packet = format!("tick {}\nack {}\n", self.tick, self.remote_tick);
+        if let Some(&(tick, checksum)) = self.checksums.back() {
+            packet.push_str(&*format!("sync {} {:x}\n", tick, checksum));
+        }
+        for input in &self.local_inputs {
+            packet.push_str(&*input.to_line());
+            packet.push('\n');
+        }
+        // the remote peer may not be started yet
+        let _ = self.socket.send(packet.as_bytes());
+    }
+}
+
+#[cfg(test)]
+fn test_peer() -> (Netplay, UdpSocket, Mutex<hlua::Lua<'static>>) {
+    let remote = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let netplay = Netplay::new("127.0.0.1:0", &*remote.local_addr().unwrap().to_string(), 0).unwrap();
+    remote.connect(netplay.socket.local_addr().unwrap()).unwrap();
+    let mut lua = hlua::Lua::new();
+    lua.execute::<()>("inputs = \"\"\nfunction input(state, scancode, virtualcode) inputs = inputs .. player .. state .. scancode .. virtualcode .. \",\" end").unwrap();
+    (netplay, remote, Mutex::new(lua))
+}
+
+#[cfg(test)]
+fn test_receive(netplay: &mut Netplay, remote: &UdpSocket, lua: &Mutex<hlua::Lua>, packet: &str) -> Option<u64> {
+    let (_, api_rx) = ::std::sync::mpsc::channel();
+    remote.send(packet.as_bytes()).unwrap();
+    ::std::thread::sleep(::std::time::Duration::from_millis(20));
+    netplay.receive(lua, &api_rx)
+}
+
+#[test]
+fn input_line_test() {
+    let input = Input {
+        tick: 12,
+        index: 3,
+        state: "pressed".into(),
+        scancode: 17,
+        virtualcode: "key1".into(),
+    };
+    let line = input.to_line();
+    assert_eq!(line, "input 12 3 pressed 17 key1");
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let parsed = Input::from_words(&words[1..]).unwrap();
+    assert_eq!(parsed.to_line(), line);
+
+    for malformed in &[
+        "12 3 pressed 17",
+        "12 3 pressed 17 key1 key2",
+        "x 3 pressed 17 key1",
+        "12 -3 pressed 17 key1",
+        "12 3 pressed 17.5 key1",
+        "12 3 down 17 key1",
+        "12 3 pressed 17 Key1",
+        "12 3 pressed 17 a\")os.execute(\"x",
+    ] {
+        let words = malformed.split_whitespace().collect::<Vec<_>>();
+        assert!(Input::from_words(&words).is_none(), "{}", malformed);
+    }
+}
+
+#[test]
+fn input_index_test() {
+    let (mut netplay, _, _) = test_peer();
+    netplay.input("pressed".into(), 1, "a".into());
+    netplay.input("released".into(), 1, "a".into());
+    netplay.local_inputs.append(&mut netplay.pending);
+    netplay.input("pressed".into(), 2, "b".into());
+    netplay.tick = 1;
+    netplay.input("pressed".into(), 3, "c".into());
+    let keys = netplay.local_inputs.iter().chain(netplay.pending.iter())
+        .map(|input| (input.tick, input.index))
+        .collect::<Vec<_>>();
+    assert_eq!(keys, vec!((0, 0), (0, 1), (0, 2), (1, 0)));
+}
+
+#[test]
+fn receive_test() {
+    let (mut netplay, remote, lua) = test_peer();
+    netplay.tick = 5;
+
+    // late inputs are executed in order of tick and index, the others are queued
+    let late = test_receive(&mut netplay, &remote, &lua,
+        "tick 3\nack 1\ninput 2 1 released 2 b\ninput 2 0 pressed 1 a\ninput 1 0 pressed 0 z\ninput 6 0 pressed 6 c\n");
+    assert_eq!(late, Some(1));
+    assert_eq!(lua.lock().unwrap().get::<String, _>("inputs").unwrap(), "1pressed0z,1pressed1a,1released2b,");
+    assert_eq!(lua.lock().unwrap().get::<u32, _>("player").unwrap(), 0);
+    assert_eq!(netplay.remote_pending.keys().cloned().collect::<Vec<_>>(), vec!((6, 0)));
+    assert_eq!((netplay.remote_tick, netplay.ack), (3, 1));
+
+    // inputs already received and malformed lines are ignored
+    let late = test_receive(&mut netplay, &remote, &lua,
+        "tick 4\nack 0\ninput 2 0 pressed 1 a\ninput 6 0 pressed 6 c\ninput 4 0 pressed 4 X\ngarbage\n");
+    assert_eq!(late, None);
+    assert_eq!(lua.lock().unwrap().get::<String, _>("inputs").unwrap(), "1pressed0z,1pressed1a,1released2b,");
+    assert_eq!(netplay.remote_pending.len(), 1);
+    assert_eq!((netplay.remote_tick, netplay.ack), (4, 1));
+}
+
+#[test]
+fn ack_test() {
+    let (mut netplay, remote, lua) = test_peer();
+    let mut world = ::specs::World::new();
+    ::resources::add_resources(&mut world);
+    ::components::register_components(&mut world);
+    netplay.snapshots.push_back((0, Snapshot::new(&world, 0)));
+
+    netplay.input("pressed".into(), 1, "a".into());
+    netplay.local_inputs.append(&mut netplay.pending);
+    netplay.tick = 1;
+    netplay.input("pressed".into(), 2, "b".into());
+    netplay.local_inputs.append(&mut netplay.pending);
+
+    // unacknowledged inputs are sent again
+    netplay.send();
+    let mut buf = vec![0; MAX_PACKET_SIZE];
+    let len = remote.recv(&mut buf).unwrap();
+    assert_eq!(String::from_utf8_lossy(&buf[..len]), "tick 1\nack 0\ninput 0 0 pressed 1 a\ninput 1 0 pressed 2 b\n");
+
+    test_receive(&mut netplay, &remote, &lua, "tick 0\nack 1\n");
+    netplay.prune();
+    assert_eq!(netplay.local_inputs.iter().map(|input| input.tick).collect::<Vec<_>>(), vec!(1));
+}
@@ -4,9 +4,13 @@ impl_component! {
     PhysicState: VecStorage,
     PhysicType: VecStorage,
     PhysicDamping: VecStorage,
-    PhysicForce: VecStorage,
+    PhysicForces: VecStorage,
+    PhysicTorque: HashMapStorage,
+    PhysicAngularDamping: HashMapStorage,
     PhysicDynamic: NullStorage,
     PhysicStatic: NullStorage,
+    PhysicBullet: NullStorage,
+    PhysicField: HashMapStorage,
     DrawPhysic: VecStorage,
 }
 
@@ -15,6 +19,9 @@ pub struct PhysicState {
     pub pos: [f32; 2],
     pub vel: [f32; 2],
     pub acc: [f32; 2],
+    pub angle: f32,
+    pub ang_vel: f32,
+    pub ang_acc: f32,
 }
 impl PhysicState {
     pub fn new(pos: [f32; 2]) -> Self {
@@ -22,6 +29,9 @@ impl PhysicState {
             pos: pos,
             vel: [0., 0.],
             acc: [0., 0.],
+            angle: 0.,
+            ang_vel: 0.,
+            ang_acc: 0.,
         }
     }
 }
@@ -33,6 +43,12 @@ pub struct PhysicType {
     pub weight: f32,
     pub group: u32,
     pub mask: u32,
+    /// sensors report overlaps but are never resolved
+    pub sensor: bool,
+    /// part of the normal velocity kept on bounce
+    pub restitution: f32,
+    /// part of the tangential velocity lost on bounce
+    pub friction: f32,
 }
 impl PhysicType {
     pub fn new_movable(group: u32, mask: u32, shape: Shape, collision: CollisionBehavior, weight: f32) -> PhysicType {
@@ -42,6 +58,9 @@ impl PhysicType {
             weight: weight,
             group: group,
             mask: mask,
+            sensor: false,
+            restitution: 1.,
+            friction: 0.,
         }
     }
     pub fn new_static(group: u32, mask: u32, shape: Shape) -> PhysicType {
@@ -51,20 +70,139 @@ impl PhysicType {
             weight: ::std::f32::MAX,
             group: group,
             mask: mask,
+            sensor: false,
+            restitution: 1.,
+            friction: 0.,
         }
     }
+    pub fn new_sensor(group: u32, mask: u32, shape: Shape) -> PhysicType {
+        PhysicType {
+            shape: shape,
+            collision: CollisionBehavior::Persist,
+            weight: ::std::f32::MAX,
+            group: group,
+            mask: mask,
+            sensor: true,
+            restitution: 1.,
+            friction: 0.,
+        }
+    }
+    /// Set restitution and friction used by the bounce collision behavior,
+    /// constructors make a perfect elastic reflector
+    pub fn with_bounce(mut self, restitution: f32, friction: f32) -> PhysicType {
+        self.restitution = restitution;
+        self.friction = friction;
+        self
+    }
 }
 
 #[derive(Clone)]
-pub struct PhysicForce {
+pub struct Force {
     pub angle: f32,
     pub strength: f32,
     pub coef: f32,
 }
+impl Force {
+    pub fn new() -> Self {
+        Force {
+            angle: 0.,
+            strength: 0.,
+            coef: 0.,
+        }
+    }
+    /// cos and sin aren't correctly rounded: the last bits may differ
+    /// across platforms, see PhysicStepping
+    pub fn vector(&self) -> [f32; 2] {
+        [self.coef*self.strength*self.angle.cos(), self.coef*self.strength*self.angle.sin()]
+    }
+}
+
+/// Forces applied to a dynamic body
+///
+/// named forces last until they are changed (the move of a character ...),
+/// transient forces (force fields ...) and impulses are cleared after each physic update
+#[derive(Clone)]
+pub struct PhysicForces {
+    pub named: Vec<(String, Force)>,
+    pub transient: [f32; 2],
+    /// change of momentum
+    pub impulse: [f32; 2],
+}
+impl PhysicForces {
+    pub fn new() -> Self {
+        PhysicForces {
+            named: Vec::new(),
+            transient: [0., 0.],
+            impulse: [0., 0.],
+        }
+    }
+    /// The force of this name, created null if it doesn't exist
+    pub fn named(&mut self, name: &str) -> &mut Force {
+        let index = match self.named.iter().position(|&(ref n, _)| n == name) {
+            Some(index) => index,
+            None => {
+                self.named.push((String::from(name), Force::new()));
+                self.named.len() - 1
+            }
+        };
+        &mut self.named[index].1
+    }
+    pub fn remove(&mut self, name: &str) {
+        self.named.retain(|&(ref n, _)| n != name);
+    }
+    pub fn add_transient(&mut self, force: [f32; 2]) {
+        self.transient[0] += force[0];
+        self.transient[1] += force[1];
+    }
+    pub fn add_impulse(&mut self, impulse: [f32; 2]) {
+        self.impulse[0] += impulse[0];
+        self.impulse[1] += impulse[1];
+    }
+    /// Sum of named and transient forces
+    pub fn total(&self) -> [f32; 2] {
+        let mut total = self.transient;
+        for &(_, ref force) in &self.named {
+            let vector = force.vector();
+            total[0] += vector[0];
+            total[1] += vector[1];
+        }
+        total
+    }
+    pub fn clear_transient(&mut self) {
+        self.transient = [0., 0.];
+        self.impulse = [0., 0.];
+    }
+}
 
 #[derive(Clone)]
 pub struct PhysicDamping(pub f32);
 
+/// Rotational counterpart of Force,
+/// strength is positive for counterclockwise rotation
+#[derive(Clone)]
+pub struct PhysicTorque {
+    pub strength: f32,
+    pub coef: f32,
+}
+
+/// Rotational counterpart of PhysicDamping
+#[derive(Clone)]
+pub struct PhysicAngularDamping(pub f32);
+
+/// Attracts or repulses dynamic bodies within radius, see field_force
+///
+/// bodies are filtered like in ShapeCast: their group must be in mask
+/// and group in their mask, the force is added to their transient forces
+/// so bodies without PhysicForces aren't affected
+#[derive(Clone)]
+pub struct PhysicField {
+    pub strength: f32,
+    pub radius: f32,
+    pub falloff: Falloff,
+    pub group: u32,
+    pub mask: u32,
+}
+
 #[derive(Clone)]
 pub struct DrawPhysic {
     pub border: Option<(f32, [f32;4])>,
@@ -74,3 +212,6 @@ pub struct DrawPhysic {
 pub struct PhysicDynamic;
 #[derive(Clone,Copy,Default)]
 pub struct PhysicStatic;
+/// Fast dynamic entity: its movement is swept so it can't tunnel through thin walls
+#[derive(Clone,Copy,Default)]
+pub struct PhysicBullet;
@@ -4,14 +4,17 @@ use graphics::{self, Layer};
 use super::*;
 use super::components::*;
 
+const SEGMENT_WIDTH: f32 = 0.1;
+
 pub fn draw_physic(world: &mut specs::World, frame: &mut graphics::Frame) {
     let draws = world.read::<DrawPhysic>();
     let states = world.read::<PhysicState>();
     let types = world.read::<PhysicType>();
 
     for (draw, state, typ) in (&draws, &states, &types).iter() {
+        let shape = typ.shape.rotated(state.angle);
         if let Some((thickness, border_color)) = draw.border {
-            match typ.shape {
+            match shape {
                 Shape::Circle(radius) => {
                     frame.draw_circle(state.pos[0], state.pos[1], radius, Layer::Middle, border_color);
                     frame.draw_circle(state.pos[0], state.pos[1], radius - thickness, Layer::Middle, draw.color);
@@ -20,11 +23,29 @@ pub fn draw_physic(world: &mut specs::World, frame: &mut graphics::Frame) {
                     frame.draw_rectangle(state.pos[0], state.pos[1], width, height, Layer::Middle, border_color);
                     frame.draw_rectangle(state.pos[0], state.pos[1], width - 2.*thickness, height - 2.*thickness, Layer::Middle, draw.color);
                 },
+                Shape::Polygon(..) => {
+                    let vertices = shape.vertices(state.pos);
+                    frame.draw_polygon(&vertices, Layer::Middle, draw.color);
+                    for i in 0..vertices.len() {
+                        let next = vertices[(i + 1) % vertices.len()];
+                        frame.draw_line(vertices[i], next, thickness, Layer::Middle, border_color);
+                    }
+                },
+                Shape::Segment(..) => {
+                    let vertices = shape.vertices(state.pos);
+                    frame.draw_line(vertices[0], vertices[1], SEGMENT_WIDTH + 2.*thickness, Layer::Middle, border_color);
+                    frame.draw_line(vertices[0], vertices[1], SEGMENT_WIDTH, Layer::Middle, draw.color);
+                },
             }
         } else {
-            match typ.shape {
+            match shape {
                 Shape::Circle(radius) => frame.draw_circle(state.pos[0], state.pos[1], radius, Layer::Middle, draw.color),
                 Shape::Rectangle(width, height) => frame.draw_rectangle(state.pos[0], state.pos[1], width, height, Layer::Middle, draw.color),
+                Shape::Polygon(..) => frame.draw_polygon(&shape.vertices(state.pos), Layer::Middle, draw.color),
+                Shape::Segment(..) => {
+                    let vertices = shape.vertices(state.pos);
+                    frame.draw_line(vertices[0], vertices[1], SEGMENT_WIDTH, Layer::Middle, draw.color);
+                },
             }
         }
     }
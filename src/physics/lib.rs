@@ -1,6 +1,8 @@
 extern crate specs;
 extern crate fnv;
 
+use std::f32;
+
 #[macro_use] extern crate ruga_utils as utils;
 extern crate ruga_graphics as graphics;
 
@@ -8,15 +10,113 @@ pub mod components;
 pub mod resources;
 pub mod update_systems;
 pub mod draw_systems;
+pub mod navigation;
 
 const PHYSIC_RATE: f32 = 0.9;
 
+/// Also valid for torque and angular damping with angular velocity
+/// as the weight is used as moment of inertia
 pub fn compute_force_damping(velocity: f32, time_to_reach_v_max: f32, weight: f32) -> (f32, f32) {
     let damping = -weight * (1. - PHYSIC_RATE).ln() / time_to_reach_v_max;
     let force = velocity * damping;
     (force, damping)
 }
 
+/// Velocity after bouncing on a surface, normal points out of the surface
+///
+/// the normal component is reflected and scaled by restitution,
+/// the tangential component is reduced by friction
+pub fn bounce_velocity(vel: [f32; 2], normal: [f32; 2], restitution: f32, friction: f32) -> [f32; 2] {
+    let norm = (normal[0].powi(2) + normal[1].powi(2)).sqrt();
+    if norm == 0. {
+        return vel;
+    }
+    let n = [normal[0] / norm, normal[1] / norm];
+
+    let normal_vel = vel[0] * n[0] + vel[1] * n[1];
+    if normal_vel >= 0. {
+        // already moving away from the surface
+        return vel;
+    }
+
+    let tangent = [vel[0] - normal_vel * n[0], vel[1] - normal_vel * n[1]];
+    [tangent[0] * (1. - friction) - restitution * normal_vel * n[0],
+     tangent[1] * (1. - friction) - restitution * normal_vel * n[1]]
+}
+
+/// Decrease of the strength of a force field with distance
+#[derive(Clone, Copy, PartialEq)]
+pub enum Falloff {
+    Constant,
+    /// from full strength at the center to zero at the radius
+    Linear,
+    /// full strength up to distance 1
+    InverseSquare,
+}
+
+impl Falloff {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Falloff::Constant => "constant",
+            Falloff::Linear => "linear",
+            Falloff::InverseSquare => "inverse_square",
+        }
+    }
+    pub fn from_str(s: &str) -> Option<Falloff> {
+        match s {
+            "constant" => Some(Falloff::Constant),
+            "linear" => Some(Falloff::Linear),
+            "inverse_square" => Some(Falloff::InverseSquare),
+            _ => None,
+        }
+    }
+}
+
+/// Force applied by a field at center on a body at pos,
+/// attraction if strength is positive, repulsion otherwise
+pub fn field_force(center: [f32; 2], pos: [f32; 2], strength: f32, radius: f32, falloff: Falloff) -> [f32; 2] {
+    let dx = center[0] - pos[0];
+    let dy = center[1] - pos[1];
+    let distance = (dx.powi(2) + dy.powi(2)).sqrt();
+    if distance == 0. || distance > radius {
+        return [0., 0.];
+    }
+    let factor = match falloff {
+        Falloff::Constant => 1.,
+        Falloff::Linear => 1. - distance / radius,
+        Falloff::InverseSquare => 1. / distance.max(1.).powi(2),
+    };
+    let f = strength * factor / distance;
+    [f * dx, f * dy]
+}
+
+/// Round value to the nearest multiple of quantum
+pub fn quantize(value: f32, quantum: f32) -> f32 {
+    (value / quantum).round() * quantum
+}
+
+/// Hash of the physic state of all entities, in entity order
+///
+/// used to detect desync between peers: worlds with different checksums have
+/// different positions, velocities or angles, worlds with the same checksum
+/// most likely have the same ones
+pub fn checksum(world: &specs::World) -> u64 {
+    use std::hash::Hasher;
+    use specs::Join;
+
+    let states = world.read::<components::PhysicState>();
+    let entities = world.entities();
+
+    let mut hasher = fnv::FnvHasher::default();
+    for (state, entity) in (&states, &entities).iter() {
+        hasher.write_u32(entity.get_id());
+        for value in &[state.pos[0], state.pos[1], state.vel[0], state.vel[1], state.angle, state.ang_vel] {
+            hasher.write_u32(value.to_bits());
+        }
+    }
+    hasher.finish()
+}
+
 pub struct Resolution {
     pub dx: f32,
     pub dy: f32,
@@ -29,9 +129,11 @@ impl Resolution {
             dy: 0.,
         }
     }
+    /// Keep the largest component on each axis,
+    /// on equal magnitude the positive one wins so the result doesn't depend on push order
     pub fn push(&mut self, res: Resolution) {
-        if res.dx.abs() > self.dx.abs() { self.dx = res.dx; }
-        if res.dy.abs() > self.dy.abs() { self.dy = res.dy; }
+        if res.dx.abs() > self.dx.abs() || (res.dx.abs() == self.dx.abs() && res.dx > self.dx) { self.dx = res.dx; }
+        if res.dy.abs() > self.dy.abs() || (res.dy.abs() == self.dy.abs() && res.dy > self.dy) { self.dy = res.dy; }
     }
 }
 
@@ -74,26 +176,99 @@ impl Collision {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ContactState {
+    /// the entities weren't in contact on previous update
+    Started,
+    Persisting,
+    /// the entities aren't in contact anymore
+    Ended,
+}
+
+#[derive(Clone)]
+pub struct CollisionEvent {
+    pub a: ::specs::Entity,
+    pub b: ::specs::Entity,
+    /// unit vector to move a out of b
+    pub normal: [f32; 2],
+    pub depth: f32,
+    pub state: ContactState,
+    /// one of the entities is a sensor
+    pub sensor: bool,
+}
+
 #[derive(Clone)]
 pub enum Shape {
     /// radius
     Circle(f32),
     /// width and height
     Rectangle(f32, f32),
+    /// vertices relative to the position and angle of rotation,
+    /// the polygon must be convex
+    Polygon(Vec<[f32; 2]>, f32),
+    /// length and angle, the segment is centered on the position
+    Segment(f32, f32),
 }
 impl Shape {
+    /// Half width and half height of the axis aligned bounding box
+    pub fn half_extents(&self) -> (f32, f32) {
+        match *self {
+            Shape::Circle(r) => (r, r),
+            Shape::Rectangle(w, h) => (w / 2., h / 2.),
+            Shape::Polygon(..) | Shape::Segment(..) => {
+                self.vertices([0., 0.]).iter().fold((0f32, 0f32), |(w2, h2), p| {
+                    (w2.max(p[0].abs()), h2.max(p[1].abs()))
+                })
+            },
+        }
+    }
+    /// Absolute coordinates of the vertices, circle has none
+    pub fn vertices(&self, pos: [f32; 2]) -> Vec<[f32; 2]> {
+        match *self {
+            Shape::Circle(_) => vec!(),
+            Shape::Rectangle(w, h) => vec!(
+                [pos[0] - w / 2., pos[1] - h / 2.],
+                [pos[0] + w / 2., pos[1] - h / 2.],
+                [pos[0] + w / 2., pos[1] + h / 2.],
+                [pos[0] - w / 2., pos[1] + h / 2.],
+            ),
+            Shape::Polygon(ref points, angle) => {
+                let (sin, cos) = angle.sin_cos();
+                points.iter()
+                    .map(|p| [pos[0] + p[0] * cos - p[1] * sin, pos[1] + p[0] * sin + p[1] * cos])
+                    .collect()
+            },
+            Shape::Segment(length, angle) => {
+                let dx = length / 2. * angle.cos();
+                let dy = length / 2. * angle.sin();
+                vec!([pos[0] - dx, pos[1] - dy], [pos[0] + dx, pos[1] + dy])
+            },
+        }
+    }
+    /// The shape rotated by angle around its position
+    pub fn rotated(&self, angle: f32) -> Shape {
+        if angle == 0. {
+            return self.clone();
+        }
+        match *self {
+            Shape::Circle(r) => Shape::Circle(r),
+            Shape::Rectangle(w, h) => {
+                let (w2, h2) = (w / 2., h / 2.);
+                Shape::Polygon(vec!([-w2, -h2], [w2, -h2], [w2, h2], [-w2, h2]), angle)
+            },
+            Shape::Polygon(ref points, a) => Shape::Polygon(points.clone(), a + angle),
+            Shape::Segment(length, a) => Shape::Segment(length, a + angle),
+        }
+    }
     pub fn cells(&self, pos: [f32; 2]) -> Vec<[i32; 2]> {
         use ::std::f32::EPSILON;
 
-        let (w2, h2) = match *self {
-            Shape::Circle(r) => (r, r),
-            Shape::Rectangle(w, h) => (w / 2., h / 2.),
-        };
+        let (w2, h2) = self.half_extents();
 
         let min_x = (pos[0] - w2 + EPSILON).floor() as i32;
-        let max_x = (pos[0] + w2 - EPSILON).floor() as i32;
+        let max_x = ((pos[0] + w2 - EPSILON).floor() as i32).max(min_x);
         let min_y = (pos[1] - h2 + EPSILON).floor() as i32;
-        let max_y = (pos[1] + h2 - EPSILON).floor() as i32;
+        let max_y = ((pos[1] + h2 - EPSILON).floor() as i32).max(min_y);
 
         let mut cells = Vec::new();
         for x in min_x..max_x + 1 {
@@ -109,6 +284,7 @@ impl Shape {
         match *self {
             Circle(r) => circle_raycast(pos[0], pos[1], r, a, b, c),
             Rectangle(w, h) => bounding_box_raycast(pos[0], pos[1], w, h, a, b, c),
+            Polygon(..) | Segment(..) => polygon_raycast(&self.vertices(pos), a, b, c),
         }
     }
 }
@@ -223,6 +399,50 @@ fn circle_raycast(x: f32, y: f32, radius: f32, a: f32, b: f32, c: f32) -> Option
     }
 }
 
+/// the coordinate of the intersections (if some) of a convex polygon (or a segment)
+/// and the line of equation ax+by+c=0
+fn polygon_raycast(vertices: &[[f32; 2]], a: f32, b: f32, c: f32) -> Option<(f32, f32, f32, f32)> {
+    if a == 0. && b == 0. {
+        panic!("invalid line equation")
+    }
+
+    let edges = if vertices.len() == 2 { 1 } else { vertices.len() };
+    let mut intersections = vec!();
+    for i in 0..edges {
+        let p = vertices[i];
+        let q = vertices[(i + 1) % vertices.len()];
+        let fp = a * p[0] + b * p[1] + c;
+        let fq = a * q[0] + b * q[1] + c;
+        if fp == 0. && fq == 0. {
+            intersections.push(p);
+            intersections.push(q);
+        } else if fp * fq <= 0. {
+            let t = fp / (fp - fq);
+            intersections.push([p[0] + t * (q[0] - p[0]), p[1] + t * (q[1] - p[1])]);
+        }
+    }
+
+    // sort intersections along the line: by abscisse or by ordinate if vertical
+    let along = |p: &[f32; 2]| if b != 0. { p[0] } else { p[1] };
+    let first = intersections.iter().cloned().fold(None, |min: Option<[f32; 2]>, p| {
+        match min {
+            Some(min) if along(&min) <= along(&p) => Some(min),
+            _ => Some(p),
+        }
+    });
+    let last = intersections.iter().cloned().fold(None, |max: Option<[f32; 2]>, p| {
+        match max {
+            Some(max) if along(&max) >= along(&p) => Some(max),
+            _ => Some(p),
+        }
+    });
+
+    match (first, last) {
+        (Some(first), Some(last)) => Some((first[0], first[1], last[0], last[1])),
+        _ => None,
+    }
+}
+
 /// The line of equation ax + by + c = 0 that pass through the two points
 fn line_equation_from_points(p: [f32; 2], q: [f32; 2]) -> (f32, f32, f32) {
     let (a, b) = if (p[0] - q[0]).abs() > (p[1] - q[1]).abs() {
@@ -320,6 +540,9 @@ pub fn shape_collision(a_pos: [f32;2], a_shape: &Shape, b_pos: [f32;2], b_shape:
         (&Circle(a_radius), &Rectangle(b_w, b_h)) => circle_rectangle_collision(a_pos, a_radius, b_pos, b_w, b_h),
         (&Rectangle(a_w, a_h), &Rectangle(b_w, b_h)) => rectangle_rectangle_collision(a_pos, a_w, a_h, b_pos, b_w, b_h),
         (&Rectangle(a_w, a_h), &Circle(b_radius)) => circle_rectangle_collision(b_pos, b_radius, a_pos, a_w, a_h).map(|col| col.opposite()),
+        (&Circle(a_radius), _) => circle_polygon_collision(a_pos, a_radius, &b_shape.vertices(b_pos)),
+        (_, &Circle(b_radius)) => circle_polygon_collision(b_pos, b_radius, &a_shape.vertices(a_pos)).map(|col| col.opposite()),
+        _ => polygon_polygon_collision(&a_shape.vertices(a_pos), &b_shape.vertices(b_pos)),
     }
 }
 
@@ -329,12 +552,19 @@ fn circle_circle_collision(a_pos: [f32;2], a_rad: f32, b_pos: [f32;2], b_rad: f3
     let dn2 = dx.powi(2) + dy.powi(2);
     let rad = a_rad+b_rad;
     if dn2 < rad.powi(2) {
-        let angle = dy.atan2(dx);
+        // sqrt and division are correctly rounded so this function gives the same
+        // result on every platform, trigonometry used elsewhere doesn't
         let dn = dn2.sqrt();
         let delta = rad - dn;
+        if dn == 0. {
+            return Some(Collision {
+                delta_x: delta,
+                delta_y: 0.,
+            });
+        }
         Some(Collision {
-            delta_x: delta*angle.cos(),
-            delta_y: delta*angle.sin(),
+            delta_x: delta*dx/dn,
+            delta_y: delta*dy/dn,
         })
     } else {
         None
@@ -417,6 +647,82 @@ fn rectangle_rectangle_collision(a_pos: [f32;2], a_width: f32, a_height: f32, b_
         }
     }
 }
+/// The normals of the edges of a convex polygon (or a segment)
+fn edge_normals(vertices: &[[f32; 2]]) -> Vec<[f32; 2]> {
+    let edges = if vertices.len() == 2 { 1 } else { vertices.len() };
+    (0..edges).filter_map(|i| {
+        let p = vertices[i];
+        let q = vertices[(i + 1) % vertices.len()];
+        let normal = [p[1] - q[1], q[0] - p[0]];
+        let norm = (normal[0].powi(2) + normal[1].powi(2)).sqrt();
+        if norm > 0. {
+            Some([normal[0] / norm, normal[1] / norm])
+        } else {
+            None
+        }
+    }).collect()
+}
+
+fn project(vertices: &[[f32; 2]], axis: [f32; 2]) -> (f32, f32) {
+    vertices.iter().fold((f32::MAX, f32::MIN), |(min, max), p| {
+        let d = p[0] * axis[0] + p[1] * axis[1];
+        (min.min(d), max.max(d))
+    })
+}
+
+/// Separating axis theorem: keep the axis with the smallest overlap
+/// and return the vector to move a out of b along this axis
+fn separating_axis_collision<F: Fn([f32; 2]) -> (f32, f32), G: Fn([f32; 2]) -> (f32, f32)>(axes: &[[f32; 2]], project_a: F, project_b: G) -> Option<Collision> {
+    let mut best: Option<Collision> = None;
+    let mut best_overlap = f32::MAX;
+    for &axis in axes {
+        let (a_min, a_max) = project_a(axis);
+        let (b_min, b_max) = project_b(axis);
+        if a_max <= b_min || b_max <= a_min {
+            return None;
+        }
+        let (overlap, sign) = if b_max - a_min < a_max - b_min {
+            (b_max - a_min, 1.)
+        } else {
+            (a_max - b_min, -1.)
+        };
+        if overlap < best_overlap {
+            best_overlap = overlap;
+            best = Some(Collision {
+                delta_x: sign * overlap * axis[0],
+                delta_y: sign * overlap * axis[1],
+            });
+        }
+    }
+    best
+}
+
+fn polygon_polygon_collision(a: &[[f32; 2]], b: &[[f32; 2]]) -> Option<Collision> {
+    let mut axes = edge_normals(a);
+    axes.append(&mut edge_normals(b));
+    separating_axis_collision(&axes, |axis| project(a, axis), |axis| project(b, axis))
+}
+
+fn circle_polygon_collision(a_pos: [f32; 2], a_radius: f32, b: &[[f32; 2]]) -> Option<Collision> {
+    let mut axes = edge_normals(b);
+
+    let closest = b.iter().cloned().fold(b[0], |closest, p| {
+        let d_closest = (closest[0] - a_pos[0]).powi(2) + (closest[1] - a_pos[1]).powi(2);
+        let d = (p[0] - a_pos[0]).powi(2) + (p[1] - a_pos[1]).powi(2);
+        if d < d_closest { p } else { closest }
+    });
+    let axis = [a_pos[0] - closest[0], a_pos[1] - closest[1]];
+    let norm = (axis[0].powi(2) + axis[1].powi(2)).sqrt();
+    if norm > 0. {
+        axes.push([axis[0] / norm, axis[1] / norm]);
+    }
+
+    separating_axis_collision(&axes, |axis| {
+        let d = a_pos[0] * axis[0] + a_pos[1] * axis[1];
+        (d - a_radius, d + a_radius)
+    }, |axis| project(b, axis))
+}
+
 #[test]
 fn circle_raycast_test() {
     // for a == 0
@@ -432,6 +738,71 @@ fn circle_raycast_test() {
                circle_raycast(0., 0., 2f32.sqrt(), 1., -1., 0.));
 }
 
+#[test]
+fn field_force_test() {
+    // attraction toward the center
+    assert_eq!([2., 0.], field_force([0., 0.], [-0.5, 0.], 2., 3., Falloff::Constant));
+
+    // repulsion
+    assert_eq!([0., -2.], field_force([0., 0.], [0., -0.5], -2., 3., Falloff::Constant));
+
+    // no force outside of the radius nor at the center
+    assert_eq!([0., 0.], field_force([0., 0.], [4., 0.], 2., 3., Falloff::Constant));
+    assert_eq!([0., 0.], field_force([0., 0.], [0., 0.], 2., 3., Falloff::Constant));
+
+    // falloff
+    assert_eq!([-1., 0.], field_force([0., 0.], [1.5, 0.], 2., 3., Falloff::Linear));
+    assert_eq!([-0.5, 0.], field_force([0., 0.], [2., 0.], 2., 3., Falloff::InverseSquare));
+    assert_eq!([-2., 0.], field_force([0., 0.], [0.5, 0.], 2., 3., Falloff::InverseSquare));
+}
+
+#[test]
+fn physic_forces_test() {
+    use components::{Force, PhysicForces};
+
+    let mut forces = PhysicForces::new();
+    *forces.named("move") = Force {
+        angle: 0.,
+        strength: 1.,
+        coef: 2.,
+    };
+    forces.named("wind").coef = 1.;
+    forces.named("wind").strength = 1.;
+    forces.named("wind").angle = f32::consts::FRAC_PI_2;
+    forces.add_transient([1., 0.]);
+    forces.add_impulse([0., 3.]);
+
+    let total = forces.total();
+    assert!((total[0] - 3.).abs() < 1e-6);
+    assert!((total[1] - 1.).abs() < 1e-6);
+
+    // named forces stay after the update
+    forces.clear_transient();
+    forces.remove("wind");
+    assert_eq!([2., 0.], forces.total());
+    assert_eq!([0., 0.], forces.impulse);
+}
+
+#[test]
+fn bounce_velocity_test() {
+    // perfect reflection on an horizontal surface
+    assert_eq!([1., 2.], bounce_velocity([1., -2.], [0., 1.], 1., 0.));
+
+    // normal doesn't need to be normalized
+    assert_eq!([1., 2.], bounce_velocity([1., -2.], [0., 0.5], 1., 0.));
+
+    // restitution and friction
+    assert_eq!([0.5, 1.], bounce_velocity([1., -2.], [0., 1.], 0.5, 0.5));
+
+    // no bounce when moving away from the surface
+    assert_eq!([1., 2.], bounce_velocity([1., 2.], [0., 1.], 0.5, 0.5));
+
+    // diagonal surface
+    let vel = bounce_velocity([-1., 0.], [1., 1.], 1., 0.);
+    assert!(vel[0].abs() < 1e-6);
+    assert!((vel[1] - 1.).abs() < 1e-6);
+}
+
 #[test]
 fn test_bounding_box_raycast() {
     // for a == 0
@@ -473,3 +844,67 @@ fn test_bounding_box_raycast() {
     assert_eq!(Some((-4., -1.96, 2., -2.02)),
                bounding_box_raycast(-1., -2., 6., 2., 0.01, 1., 2.));
 }
+
+#[test]
+fn polygon_collision_test() {
+    // diamond against square
+    let diamond = Shape::Polygon(vec!([1., 0.], [0., 1.], [-1., 0.], [0., -1.]), 0.);
+    let square = Shape::Rectangle(2., 2.);
+    let collision = shape_collision([1.5, 0.], &diamond, [0., 0.], &square).unwrap();
+    assert!((collision.delta_x - 0.5).abs() < 1e-6);
+    assert!(collision.delta_y.abs() < 1e-6);
+    assert!(shape_collision([2.5, 0.], &diamond, [0., 0.], &square).is_none());
+
+    // rotated square is a diamond
+    let rotated = Shape::Polygon(vec!([-1., -1.], [1., -1.], [1., 1.], [-1., 1.]), f32::consts::FRAC_PI_4);
+    assert!(shape_collision([0., 2.5], &rotated, [0., 0.], &square).is_none());
+    assert!(shape_collision([0., 2.3], &rotated, [0., 0.], &square).is_some());
+
+    // segment across circle
+    let segment = Shape::Segment(4., f32::consts::FRAC_PI_4);
+    let collision = shape_collision([0., 0.5], &Shape::Circle(1.), [0., 0.], &segment).unwrap();
+    assert!(collision.delta_y > 0.);
+    assert!(shape_collision([0., 1.5], &Shape::Circle(1.), [0., 0.], &segment).is_none());
+}
+
+#[test]
+fn polygon_raycast_test() {
+    // horizontal line y = 0 through a square
+    let square = Shape::Polygon(vec!([-1., -1.], [1., -1.], [1., 1.], [-1., 1.]), 0.);
+    assert_eq!(Some((-1., 0., 1., 0.)), square.raycast([0., 0.], (0., -1., 0.)));
+    assert_eq!(None, square.raycast([0., 3.], (0., -1., 0.)));
+
+    // vertical segment x = 0 crossed by y = x
+    let segment = Shape::Segment(2., f32::consts::FRAC_PI_2);
+    let (x0, y0, x1, y1) = segment.raycast([0., 0.], (1., -1., 0.)).unwrap();
+    assert!(x0.abs() < 1e-6 && y0.abs() < 1e-6 && x1.abs() < 1e-6 && y1.abs() < 1e-6);
+}
+
+#[test]
+fn resolution_push_test() {
+    // same result whatever the order of pushes
+    let mut a = Resolution::none();
+    a.push(Resolution { dx: -1., dy: 0.5 });
+    a.push(Resolution { dx: 1., dy: -0.5 });
+    let mut b = Resolution::none();
+    b.push(Resolution { dx: 1., dy: -0.5 });
+    b.push(Resolution { dx: -1., dy: 0.5 });
+    assert_eq!((a.dx, a.dy), (b.dx, b.dy));
+    assert_eq!((a.dx, a.dy), (1., 0.5));
+}
+
+#[test]
+fn checksum_test() {
+    let build = |x: f32| {
+        let mut world = specs::World::new();
+        world.register::<components::PhysicState>();
+        world.create_now().with(components::PhysicState::new([0., 0.])).build();
+        world.create_now().with(components::PhysicState::new([x, 1.])).build();
+        world
+    };
+    assert_eq!(checksum(&build(1.)), checksum(&build(1.)));
+    assert!(checksum(&build(1.)) != checksum(&build(1.0001)));
+
+    assert_eq!(quantize(0.126, 0.25), 0.25);
+    assert_eq!(quantize(-0.124, 0.25), 0.);
+}
@@ -0,0 +1,372 @@
+//! Navigation grid and A* path finding
+//!
+//! The grid covers the static entities of the physic world with a margin, a node
+//! is walkable if a circle of the agent radius at its position doesn't collide
+//! with them. Path ends outside of the grid are moved to its border: there is
+//! nothing to avoid out there. Grids are cached for each radius and mask, they are
+//! rebuilt when static entities change and the least recently used is dropped
+//! when the cache is full.
+
+use std::collections::BinaryHeap;
+use std::cmp::Ordering;
+use std::f32;
+
+use fnv::FnvHashMap;
+
+use super::*;
+use super::resources::PhysicWorld;
+
+/// Distance between two neighbour nodes
+pub const NAVIGATION_RESOLUTION: f32 = 0.5;
+/// Number of nodes added around static entities, besides the agent radius
+const NAVIGATION_MARGIN: i32 = 4;
+/// Grids with more nodes aren't built, the path isn't found
+const NAVIGATION_MAX_NODES: i64 = 1 << 20;
+/// Number of grids kept in cache
+const NAVIGATION_MAX_GRIDS: usize = 8;
+/// Added to the agent radius for walkable nodes so that they are in line of sight of each other
+const NAVIGATION_CLEARANCE: f32 = 0.05;
+
+/// Number of nodes added around static entities for an agent of radius
+fn margin(radius: f32) -> i32 {
+    NAVIGATION_MARGIN + (radius / NAVIGATION_RESOLUTION).ceil().min(NAVIGATION_MAX_NODES as f32) as i32
+}
+
+/// Lowest and highest nodes around the static entities, none if there is no static entity
+fn grid_bounds(physic_world: &PhysicWorld, margin: i32) -> Option<([i32; 2], [i32; 2])> {
+    let nodes_per_cell = (1. / NAVIGATION_RESOLUTION) as i32;
+    let mut min = [i32::max_value(); 2];
+    let mut max = [i32::min_value(); 2];
+    for cell in physic_world.inert.keys() {
+        for i in 0..2 {
+            min[i] = min[i].min(cell[i] * nodes_per_cell);
+            max[i] = max[i].max((cell[i] + 1) * nodes_per_cell);
+        }
+    }
+    if min[0] > max[0] {
+        None
+    } else {
+        Some(([min[0] - margin, min[1] - margin], [max[0] + margin, max[1] + margin]))
+    }
+}
+
+struct NavigationGrid {
+    static_version: u64,
+    /// value of Navigation::uses when the grid was last used
+    last_use: u64,
+    /// coordinates of the first node
+    origin: [i32; 2],
+    width: usize,
+    height: usize,
+    walkable: Vec<bool>,
+}
+
+impl NavigationGrid {
+    /// None if the grid would have more than NAVIGATION_MAX_NODES
+    fn new(physic_world: &PhysicWorld, radius: f32, mask: u32, (min, max): ([i32; 2], [i32; 2])) -> Option<Self> {
+        let width = max[0] as i64 + 1 - min[0] as i64;
+        let height = max[1] as i64 + 1 - min[1] as i64;
+        if width * height > NAVIGATION_MAX_NODES {
+            return None;
+        }
+        let (width, height) = (width as usize, height as usize);
+
+        let mut grid = NavigationGrid {
+            static_version: physic_world.static_version(),
+            last_use: 0,
+            origin: min,
+            width: width,
+            height: height,
+            walkable: vec![true; width * height],
+        };
+        for index in 0..width * height {
+            let shape_cast = ShapeCast {
+                pos: grid.position(index),
+                shape: Shape::Circle(radius + NAVIGATION_CLEARANCE),
+                mask: mask,
+                group: !0,
+                not: vec!(),
+            };
+            let mut blocked = false;
+            physic_world.apply_on_shape(&shape_cast, &mut |_, _| blocked = true);
+            grid.walkable[index] = !blocked;
+        }
+        Some(grid)
+    }
+    fn position(&self, index: usize) -> [f32; 2] {
+        let x = self.origin[0] + (index % self.width) as i32;
+        let y = self.origin[1] + (index / self.width) as i32;
+        [x as f32 * NAVIGATION_RESOLUTION, y as f32 * NAVIGATION_RESOLUTION]
+    }
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let (x, y) = (x - self.origin[0], y - self.origin[1]);
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some(y as usize * self.width + x as usize)
+        }
+    }
+    fn bounds(&self) -> ([i32; 2], [i32; 2]) {
+        (self.origin, [self.origin[0] + self.width as i32 - 1, self.origin[1] + self.height as i32 - 1])
+    }
+    /// Nearest position inside of the grid
+    fn clamp(&self, pos: [f32; 2]) -> [f32; 2] {
+        let (min, max) = self.bounds();
+        [
+            pos[0].max(min[0] as f32 * NAVIGATION_RESOLUTION).min(max[0] as f32 * NAVIGATION_RESOLUTION),
+            pos[1].max(min[1] as f32 * NAVIGATION_RESOLUTION).min(max[1] as f32 * NAVIGATION_RESOLUTION),
+        ]
+    }
+    fn is_walkable(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).map_or(false, |index| self.walkable[index])
+    }
+    /// Closest walkable node at most margin nodes away
+    fn nearest_walkable(&self, pos: [f32; 2], margin: i32) -> Option<usize> {
+        let x = (pos[0] / NAVIGATION_RESOLUTION).round() as i32;
+        let y = (pos[1] / NAVIGATION_RESOLUTION).round() as i32;
+        for distance in 0..margin + 1 {
+            let mut best = None;
+            for dx in -distance..distance + 1 {
+                for dy in -distance..distance + 1 {
+                    if dx.abs().max(dy.abs()) != distance || !self.is_walkable(x + dx, y + dy) {
+                        continue;
+                    }
+                    let index = self.index(x + dx, y + dy).unwrap();
+                    let p = self.position(index);
+                    let d = (p[0] - pos[0]).powi(2) + (p[1] - pos[1]).powi(2);
+                    if best.map_or(true, |(best_d, _)| d < best_d) {
+                        best = Some((d, index));
+                    }
+                }
+            }
+            if let Some((_, index)) = best {
+                return Some(index);
+            }
+        }
+        None
+    }
+    /// A* from node to node on 8 neighbours, diagonals can't cut corners
+    fn astar(&self, start: usize, goal: usize) -> Option<Vec<usize>> {
+        #[derive(PartialEq)]
+        struct Open {
+            estimation: f32,
+            index: usize,
+        }
+        impl Eq for Open {}
+        impl Ord for Open {
+            // lowest estimation first then lowest index for determinism
+            fn cmp(&self, other: &Open) -> Ordering {
+                other.estimation.partial_cmp(&self.estimation)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| other.index.cmp(&self.index))
+            }
+        }
+        impl PartialOrd for Open {
+            fn partial_cmp(&self, other: &Open) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        let goal_pos = [(goal % self.width) as i32, (goal / self.width) as i32];
+        let heuristic = |index: usize| {
+            let dx = ((index % self.width) as i32 - goal_pos[0]).abs() as f32;
+            let dy = ((index / self.width) as i32 - goal_pos[1]).abs() as f32;
+            dx.max(dy) + (f32::consts::SQRT_2 - 1.) * dx.min(dy)
+        };
+
+        let mut cost = vec![f32::INFINITY; self.walkable.len()];
+        let mut came_from = vec![usize::max_value(); self.walkable.len()];
+        let mut open = BinaryHeap::new();
+        cost[start] = 0.;
+        open.push(Open { estimation: heuristic(start), index: start });
+
+        while let Some(Open { index, .. }) = open.pop() {
+            if index == goal {
+                let mut path = vec!(goal);
+                let mut current = goal;
+                while current != start {
+                    current = came_from[current];
+                    path.push(current);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let x = self.origin[0] + (index % self.width) as i32;
+            let y = self.origin[1] + (index / self.width) as i32;
+            for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                if !self.is_walkable(x + dx, y + dy) {
+                    continue;
+                }
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && !(self.is_walkable(x + dx, y) && self.is_walkable(x, y + dy)) {
+                    continue;
+                }
+                let neighbour = self.index(x + dx, y + dy).unwrap();
+                let new_cost = cost[index] + if diagonal { f32::consts::SQRT_2 } else { 1. };
+                if new_cost < cost[neighbour] {
+                    cost[neighbour] = new_cost;
+                    came_from[neighbour] = index;
+                    open.push(Open { estimation: new_cost + heuristic(neighbour), index: neighbour });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// No static entity of mask between from and to for an agent of radius
+///
+/// rays are cast from the center and both sides of the agent
+pub fn line_of_sight(physic_world: &PhysicWorld, from: [f32; 2], to: [f32; 2], radius: f32, mask: u32) -> bool {
+    let length = ((to[0] - from[0]).powi(2) + (to[1] - from[1]).powi(2)).sqrt();
+    if length == 0. {
+        return true;
+    }
+    let angle = (to[1] - from[1]).atan2(to[0] - from[0]);
+    let normal = [-angle.sin(), angle.cos()];
+    for &offset in &[0., radius, -radius] {
+        let ray = RayCast {
+            origin: [from[0] + offset * normal[0], from[1] + offset * normal[1]],
+            angle: angle,
+            length: length,
+            mask: mask,
+            group: !0,
+            not: vec!(),
+        };
+        let mut blocked = false;
+        physic_world.raycast(&ray, &mut |_| {
+            blocked = true;
+            ContinueOrStop::Stop
+        });
+        if blocked {
+            return false;
+        }
+    }
+    true
+}
+
+/// Path finding for agents, see module documentation
+pub struct Navigation {
+    grids: FnvHashMap<(u32, u32), NavigationGrid>,
+    /// number of paths searched on a grid
+    uses: u64,
+}
+
+impl Navigation {
+    pub fn new() -> Self {
+        Navigation {
+            grids: FnvHashMap::default(),
+            uses: 0,
+        }
+    }
+    /// Smoothed path from `from` to `to` avoiding static entities of mask
+    /// for an agent of radius, the path starts with `from` and ends with `to`
+    pub fn find_path(&mut self, physic_world: &PhysicWorld, from: [f32; 2], to: [f32; 2], radius: f32, mask: u32) -> Option<Vec<[f32; 2]>> {
+        if line_of_sight(physic_world, from, to, radius, mask) {
+            return Some(vec!(from, to));
+        }
+
+        let key = (radius.to_bits(), mask);
+        let margin = margin(radius);
+        let rebuild = self.grids.get(&key).map_or(true, |grid| grid.static_version != physic_world.static_version());
+        if rebuild {
+            self.grids.remove(&key);
+            let grid = grid_bounds(physic_world, margin)
+                .and_then(|bounds| NavigationGrid::new(physic_world, radius, mask, bounds))?;
+            if self.grids.len() >= NAVIGATION_MAX_GRIDS {
+                let oldest = *self.grids.iter().min_by_key(|&(_, grid)| grid.last_use).unwrap().0;
+                self.grids.remove(&oldest);
+            }
+            self.grids.insert(key, grid);
+        }
+        self.uses += 1;
+        let grid = self.grids.get_mut(&key).unwrap();
+        grid.last_use = self.uses;
+        let grid = &*grid;
+
+        let start = grid.nearest_walkable(grid.clamp(from), margin)?;
+        let goal = grid.nearest_walkable(grid.clamp(to), margin)?;
+        let nodes = grid.astar(start, goal)?;
+
+        let mut path = vec!(from);
+        path.extend(nodes.iter().map(|&index| grid.position(index)));
+        path.push(to);
+
+        // keep the farthest visible point at each step
+        let mut smoothed = vec!(from);
+        let mut current = 0;
+        while current < path.len() - 1 {
+            let next = (current + 2..path.len())
+                .rev()
+                .find(|&next| line_of_sight(physic_world, path[current], path[next], radius, mask))
+                .unwrap_or(current + 1);
+            smoothed.push(path[next]);
+            current = next;
+        }
+        Some(smoothed)
+    }
+}
+
+#[test]
+fn find_path_test() {
+    let mut world = ::specs::World::new();
+    let wall = world.create_now().build();
+    let mut physic_world = PhysicWorld::new();
+    let mut navigation = Navigation::new();
+
+    // free way
+    let path = navigation.find_path(&physic_world, [0., 0.], [10., 0.], 0.5, 1).unwrap();
+    assert_eq!(path, vec!([0., 0.], [10., 0.]));
+
+    // wall in between
+    physic_world.insert_static(EntityInformation {
+        entity: wall,
+        pos: [5., 0.],
+        group: 1,
+        mask: !0,
+        shape: Shape::Rectangle(1., 6.),
+    });
+    let path = navigation.find_path(&physic_world, [0., 0.], [10., 0.], 0.5, 1).unwrap();
+    assert_eq!(path[0], [0., 0.]);
+    assert_eq!(*path.last().unwrap(), [10., 0.]);
+    assert!(path.len() > 2);
+    // outside of the wall inflated by the radius
+    for point in &path[1..path.len() - 1] {
+        assert!((point[0] - 5.).abs() >= 1. || point[1].abs() >= 3.5);
+    }
+    for segment in path.windows(2) {
+        assert!(line_of_sight(&physic_world, segment[0], segment[1], 0.5, 1));
+    }
+
+    // ends far from the wall
+    let path = navigation.find_path(&physic_world, [-10., 0.], [20., 0.], 0.5, 1).unwrap();
+    assert_eq!(path[0], [-10., 0.]);
+    assert_eq!(*path.last().unwrap(), [20., 0.]);
+    for segment in path.windows(2) {
+        assert!(line_of_sight(&physic_world, segment[0], segment[1], 0.5, 1));
+    }
+    let path = navigation.find_path(&physic_world, [-3., 25.], [12., -25.], 0.5, 1).unwrap();
+    assert!(path.len() > 2);
+    for segment in path.windows(2) {
+        assert!(line_of_sight(&physic_world, segment[0], segment[1], 0.5, 1));
+    }
+
+    // ends out of the grid are moved to its border
+    let path = navigation.find_path(&physic_world, [1., 0.], [1e5, 1e5], 0.5, 1).unwrap();
+    assert_eq!(*path.last().unwrap(), [1e5, 1e5]);
+    for segment in path.windows(2) {
+        assert!(line_of_sight(&physic_world, segment[0], segment[1], 0.5, 1));
+    }
+    // too many nodes for a huge agent
+    assert!(navigation.find_path(&physic_world, [0., 0.], [10., 0.], 1e4, 1).is_none());
+
+    // walls of other groups are ignored
+    let path = navigation.find_path(&physic_world, [0., 0.], [10., 0.], 0.5, 2).unwrap();
+    assert_eq!(path.len(), 2);
+
+    // the cache is bounded
+    for i in 0..2 * NAVIGATION_MAX_GRIDS {
+        navigation.find_path(&physic_world, [0., 0.], [10., 0.], 0.1 * i as f32, 1).unwrap();
+    }
+    assert_eq!(navigation.grids.len(), NAVIGATION_MAX_GRIDS);
+}
@@ -5,21 +5,157 @@ use specs::Join;
 use super::*;
 use super::components::*;
 
+const SWEEP_MIN_STEP: f32 = 0.05;
+const SWEEP_BISECTIONS: usize = 8;
+
 impl_resource! {
     PhysicWorld,
+    CollisionEvents,
+    PhysicStepping,
+    Navigation,
+}
+
+pub use super::navigation::Navigation;
+
+/// Options of the physic update
+///
+/// The update gives the same result for the same world on the same platform.
+/// Across platforms it doesn't: forces are computed with cos and sin which
+/// aren't correctly rounded, so results can differ in the last bits.
+/// Only quantization makes the simulation deterministic across platforms:
+/// if quantum is set then positions, velocities and angles are rounded to
+/// a multiple of it after each update so that these differences are dropped,
+/// unless they happen to cross a rounding boundary
+pub struct PhysicStepping {
+    pub quantum: Option<f32>,
+}
+impl PhysicStepping {
+    pub fn new() -> Self {
+        PhysicStepping {
+            quantum: None,
+        }
+    }
+}
+
+/// Contacts between entities during the last physic update
+///
+/// each pair of entities is reported once with a < b
+#[derive(Clone)]
+pub struct CollisionEvents {
+    pub events: Vec<CollisionEvent>,
+    current: ::fnv::FnvHashMap<(::specs::Entity, ::specs::Entity), CollisionEvent>,
+    previous: ::fnv::FnvHashMap<(::specs::Entity, ::specs::Entity), CollisionEvent>,
+}
+impl CollisionEvents {
+    pub fn new() -> Self {
+        CollisionEvents {
+            events: Vec::new(),
+            current: ::fnv::FnvHashMap::default(),
+            previous: ::fnv::FnvHashMap::default(),
+        }
+    }
+    /// Record the contact, collision must be the vector to move a out of b
+    pub fn insert(&mut self, a: ::specs::Entity, b: ::specs::Entity, collision: &Collision, sensor: bool) {
+        let depth = (collision.delta_x.powi(2) + collision.delta_y.powi(2)).sqrt();
+        let mut normal = if depth > 0. {
+            [collision.delta_x / depth, collision.delta_y / depth]
+        } else {
+            [0., 0.]
+        };
+        let (a, b) = if a < b {
+            (a, b)
+        } else {
+            normal = [-normal[0], -normal[1]];
+            (b, a)
+        };
+        let event = self.current.entry((a, b)).or_insert(CollisionEvent {
+            a: a,
+            b: b,
+            normal: normal,
+            depth: depth,
+            state: ContactState::Started,
+            sensor: sensor,
+        });
+        if depth > event.depth {
+            event.normal = normal;
+            event.depth = depth;
+        }
+    }
+    /// Replace the entities of the contacts,
+    /// the order of entities must be kept
+    pub fn map_entities<F: Fn(::specs::Entity) -> ::specs::Entity>(&mut self, map: F) {
+        for event in &mut self.events {
+            event.a = map(event.a);
+            event.b = map(event.b);
+        }
+        for contacts in &mut [&mut self.current, &mut self.previous] {
+            **contacts = contacts.drain()
+                .map(|(_, mut event)| {
+                    event.a = map(event.a);
+                    event.b = map(event.b);
+                    ((event.a, event.b), event)
+                })
+                .collect();
+        }
+    }
+    /// Compute events from contacts inserted since last flush
+    pub fn flush(&mut self) {
+        self.events.clear();
+        for (pair, event) in &self.current {
+            let mut event = event.clone();
+            if self.previous.contains_key(pair) {
+                event.state = ContactState::Persisting;
+            }
+            self.events.push(event);
+        }
+        for (pair, event) in &self.previous {
+            if !self.current.contains_key(pair) {
+                let mut event = event.clone();
+                event.state = ContactState::Ended;
+                event.depth = 0.;
+                self.events.push(event);
+            }
+        }
+        self.events.sort_by_key(|event| (event.a, event.b));
+        self.previous = ::std::mem::replace(&mut self.current, ::fnv::FnvHashMap::default());
+    }
+}
+
+fn remove_from_cell(map: &mut ::fnv::FnvHashMap<[i32; 2], Vec<EntityInformation>>, cell: &[i32; 2], entity: ::specs::Entity) {
+    let empty = if let Some(infos) = map.get_mut(cell) {
+        infos.retain(|info| info.entity != entity);
+        infos.is_empty()
+    } else {
+        false
+    };
+    if empty {
+        map.remove(cell);
+    }
 }
 
 pub struct PhysicWorld {
     pub inert: ::fnv::FnvHashMap<[i32; 2], Vec<EntityInformation>>,
     pub movable: ::fnv::FnvHashMap<[i32; 2], Vec<EntityInformation>>,
+    /// cells of movable occupied by each dynamic entity
+    movable_cells: ::fnv::FnvHashMap<::specs::Entity, Vec<[i32; 2]>>,
+    /// cells of inert occupied by each static entity
+    inert_cells: ::fnv::FnvHashMap<::specs::Entity, Vec<[i32; 2]>>,
+    /// incremented each time static entities change
+    static_version: u64,
 }
 impl PhysicWorld {
     pub fn new() -> Self {
         PhysicWorld {
             inert: ::fnv::FnvHashMap::default(),
             movable: ::fnv::FnvHashMap::default(),
+            movable_cells: ::fnv::FnvHashMap::default(),
+            inert_cells: ::fnv::FnvHashMap::default(),
+            static_version: 0,
         }
     }
+    pub fn static_version(&self) -> u64 {
+        self.static_version
+    }
     pub fn fill(&mut self, world: &::specs::World) {
         let dynamics = world.read::<PhysicDynamic>();
         let statics = world.read::<PhysicStatic>();
@@ -29,6 +165,9 @@ impl PhysicWorld {
 
         self.inert.clear();
         self.movable.clear();
+        self.movable_cells.clear();
+        self.inert_cells.clear();
+        self.static_version += 1;
 
         for (_, state, typ, entity) in (&dynamics, &states, &types, &entities).iter() {
             let info = EntityInformation {
@@ -36,7 +175,7 @@ impl PhysicWorld {
                 pos: state.pos,
                 group: typ.group,
                 mask: typ.mask,
-                shape: typ.shape.clone(),
+                shape: typ.shape.rotated(state.angle),
             };
             self.insert_dynamic(info);
         }
@@ -46,19 +185,67 @@ impl PhysicWorld {
                 pos: state.pos,
                 group: typ.group,
                 mask: typ.mask,
-                shape: typ.shape.clone(),
+                shape: typ.shape.rotated(state.angle),
             };
             self.insert_static(info);
         }
     }
+    /// Insert the entity or move it if already inserted:
+    /// only cells that the entity leaves or enters are modified
     pub fn insert_dynamic(&mut self, info: EntityInformation) {
-        for cell in info.shape.cells(info.pos) {
-            self.movable.entry(cell).or_insert(Vec::new()).push(info.clone());
+        let cells = info.shape.cells(info.pos);
+        let old_cells = self.movable_cells.remove(&info.entity).unwrap_or(Vec::new());
+
+        for cell in &old_cells {
+            if !cells.contains(cell) {
+                remove_from_cell(&mut self.movable, cell, info.entity);
+            }
+        }
+        for cell in &cells {
+            let infos = self.movable.entry(*cell).or_insert(Vec::new());
+            if old_cells.contains(cell) {
+                for other in infos.iter_mut().filter(|other| other.entity == info.entity) {
+                    *other = info.clone();
+                }
+            } else {
+                infos.push(info.clone());
+            }
+        }
+        self.movable_cells.insert(info.entity, cells);
+    }
+    pub fn remove_dynamic(&mut self, entity: ::specs::Entity) {
+        if let Some(cells) = self.movable_cells.remove(&entity) {
+            for cell in &cells {
+                remove_from_cell(&mut self.movable, cell, entity);
+            }
         }
     }
+    /// Remove all dynamic entities for which the predicate is false
+    pub fn retain_dynamic<F: FnMut(::specs::Entity) -> bool>(&mut self, mut predicate: F) {
+        let removed = self.movable_cells.keys()
+            .cloned()
+            .filter(|&entity| !predicate(entity))
+            .collect::<Vec<_>>();
+        for entity in removed {
+            self.remove_dynamic(entity);
+        }
+    }
+    /// Insert the entity, replacing its previous insertion if any
     pub fn insert_static(&mut self, info: EntityInformation) {
-        for cell in info.shape.cells(info.pos) {
-            self.inert.entry(cell).or_insert(Vec::new()).push(info.clone());
+        self.remove_static(info.entity);
+        let cells = info.shape.cells(info.pos);
+        for cell in &cells {
+            self.inert.entry(*cell).or_insert(Vec::new()).push(info.clone());
+        }
+        self.inert_cells.insert(info.entity, cells);
+    }
+    /// Only the cells occupied by the entity are modified
+    pub fn remove_static(&mut self, entity: ::specs::Entity) {
+        self.static_version += 1;
+        if let Some(cells) = self.inert_cells.remove(&entity) {
+            for cell in &cells {
+                remove_from_cell(&mut self.inert, cell, entity);
+            }
         }
     }
     /// The collision is between shape and other entity
@@ -78,6 +265,72 @@ impl PhysicWorld {
             }
         }
     }
+    /// Time of impact in ]0, 1] of the shape moving from its position to `to`
+    ///
+    /// entities that already collide with the shape at its position are ignored
+    pub fn sweep(&self, shape: &ShapeCast, to: [f32; 2]) -> Option<f32> {
+        let from = shape.pos;
+        let distance = ((to[0] - from[0]).powi(2) + (to[1] - from[1]).powi(2)).sqrt();
+        if distance == 0. {
+            return None;
+        }
+
+        let (w2, h2) = shape.shape.half_extents();
+        let dilation_x = w2.ceil() as i32;
+        let dilation_y = h2.ceil() as i32;
+
+        let null_vec = Vec::new();
+        let mut visited = HashSet::new();
+        let mut candidates = Vec::new();
+        for cell in grid_raycast(from[0], from[1], to[0], to[1]) {
+            for x in cell[0] - dilation_x..cell[0] + dilation_x + 1 {
+                for y in cell[1] - dilation_y..cell[1] + dilation_y + 1 {
+                    let inert = self.inert.get(&[x, y]).unwrap_or(&null_vec).iter();
+                    let movable = self.movable.get(&[x, y]).unwrap_or(&null_vec).iter();
+
+                    for entity in inert.chain(movable) {
+                        if shape.not.contains(&entity.entity) { continue; }
+                        if entity.group & shape.mask == 0 { continue; }
+                        if entity.mask & shape.group == 0 { continue; }
+                        if !visited.insert(entity.entity) { continue; }
+                        if shape_collision(from, &shape.shape, entity.pos, &entity.shape).is_some() { continue; }
+                        candidates.push(entity);
+                    }
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let overlap = |t: f32| {
+            let pos = [from[0] + t * (to[0] - from[0]), from[1] + t * (to[1] - from[1])];
+            candidates.iter().any(|entity| {
+                shape_collision(pos, &shape.shape, entity.pos, &entity.shape).is_some()
+            })
+        };
+
+        let step = w2.min(h2).max(SWEEP_MIN_STEP);
+        let samples = (distance / step).ceil() as usize;
+        let mut free = 0.;
+        for i in 1..samples + 1 {
+            let t = i as f32 / samples as f32;
+            if overlap(t) {
+                let mut hit = t;
+                for _ in 0..SWEEP_BISECTIONS {
+                    let middle = (free + hit) / 2.;
+                    if overlap(middle) {
+                        hit = middle;
+                    } else {
+                        free = middle;
+                    }
+                }
+                return Some(hit);
+            }
+            free = t;
+        }
+        None
+    }
     pub fn raycast<F: FnMut((&EntityInformation, f32, f32)) -> ContinueOrStop>(&self, ray: &RayCast, callback: &mut F) {
         use ::std::f32::consts::FRAC_PI_4;
         use ::std::f32::consts::PI;
@@ -198,3 +451,103 @@ impl PhysicWorld {
         }
     }
 }
+
+#[test]
+fn insert_dynamic_test() {
+    let mut world = ::specs::World::new();
+    let entity = world.create_now().build();
+    let mut physic_world = PhysicWorld::new();
+
+    let mut info = EntityInformation {
+        entity: entity,
+        pos: [0.5, 0.5],
+        group: 1,
+        mask: 1,
+        shape: Shape::Circle(0.2),
+    };
+    physic_world.insert_dynamic(info.clone());
+    info.pos = [0.6, 0.5];
+    physic_world.insert_dynamic(info.clone());
+    assert_eq!(physic_world.movable.len(), 1);
+    assert_eq!(physic_world.movable[&[0, 0]].len(), 1);
+    assert_eq!(physic_world.movable[&[0, 0]][0].pos, [0.6, 0.5]);
+
+    info.pos = [1.0, 0.5];
+    physic_world.insert_dynamic(info.clone());
+    assert_eq!(physic_world.movable.len(), 2);
+    assert_eq!(physic_world.movable[&[0, 0]].len(), 1);
+    assert_eq!(physic_world.movable[&[1, 0]].len(), 1);
+
+    info.pos = [5.5, 5.5];
+    physic_world.insert_dynamic(info.clone());
+    assert_eq!(physic_world.movable.len(), 1);
+    assert_eq!(physic_world.movable[&[5, 5]].len(), 1);
+
+    physic_world.remove_dynamic(entity);
+    assert!(physic_world.movable.is_empty());
+}
+
+#[test]
+fn sweep_test() {
+    let mut world = ::specs::World::new();
+    let wall = world.create_now().build();
+    let bullet = world.create_now().build();
+    let mut physic_world = PhysicWorld::new();
+
+    physic_world.insert_static(EntityInformation {
+        entity: wall,
+        pos: [5., 0.],
+        group: 1,
+        mask: 1,
+        shape: Shape::Rectangle(0.2, 4.),
+    });
+
+    let shape_cast = ShapeCast {
+        pos: [0., 0.],
+        shape: Shape::Circle(0.1),
+        mask: 1,
+        group: 1,
+        not: vec!(bullet),
+    };
+
+    // the wall is hit when the bullet reach 4.8
+    let toi = physic_world.sweep(&shape_cast, [10., 0.]).unwrap();
+    assert!((toi * 10. - 4.8).abs() < 0.01);
+
+    assert!(physic_world.sweep(&shape_cast, [4., 0.]).is_none());
+    assert!(physic_world.sweep(&shape_cast, [0., 10.]).is_none());
+}
+
+#[test]
+fn remove_static_test() {
+    let mut world = ::specs::World::new();
+    let wall = world.create_now().build();
+    let mut physic_world = PhysicWorld::new();
+    physic_world.insert_static(EntityInformation {
+        entity: wall,
+        pos: [0., 0.],
+        group: 1,
+        mask: 1,
+        shape: Shape::Rectangle(3., 1.),
+    });
+    let version = physic_world.static_version();
+    physic_world.remove_static(wall);
+    assert!(physic_world.inert.is_empty());
+    assert!(physic_world.static_version() > version);
+
+    // other entities of the cells are kept
+    let other = world.create_now().build();
+    for &entity in &[wall, other] {
+        physic_world.insert_static(EntityInformation {
+            entity: entity,
+            pos: [0., 0.],
+            group: 1,
+            mask: 1,
+            shape: Shape::Rectangle(3., 1.),
+        });
+    }
+    let cells = physic_world.inert.len();
+    physic_world.remove_static(wall);
+    assert_eq!(physic_world.inert.len(), cells);
+    assert!(physic_world.inert.values().all(|infos| infos.len() == 1 && infos[0].entity == other));
+}
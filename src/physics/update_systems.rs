@@ -1,4 +1,4 @@
-use fnv::FnvHashMap;
+use std::collections::BTreeMap;
 use specs::{self, Join};
 
 use super::*;
@@ -9,24 +9,46 @@ pub struct PhysicSystem;
 #[allow(illegal_floating_point_constant_pattern)]
 impl specs::System<::utils::UpdateContext> for PhysicSystem {
     fn run(&mut self, arg: specs::RunArg, context: ::utils::UpdateContext) {
-        use std::f32::consts::PI;
         use std::f32;
 
-        let (dynamics, mut states, dampings, forces, types, mut physic_world, entities) = arg.fetch(|world| {
+        let (dynamics, bullets, fields, mut states, dampings, mut forces, angular_dampings, torques, types, mut physic_world, mut collision_events, stepping, entities) = arg.fetch(|world| {
             (
                 world.read::<PhysicDynamic>(),
+                world.read::<PhysicBullet>(),
+                world.read::<PhysicField>(),
                 world.write::<PhysicState>(),
                 world.read::<PhysicDamping>(),
-                world.read::<PhysicForce>(),
+                world.write::<PhysicForces>(),
+                world.read::<PhysicAngularDamping>(),
+                world.read::<PhysicTorque>(),
                 world.read::<PhysicType>(),
                 world.write_resource::<PhysicWorld>(),
+                world.write_resource::<CollisionEvents>(),
+                world.read_resource::<PhysicStepping>(),
                 world.entities(),
             )
         });
 
         let dt = context.dt;
 
-        let mut resolutions = FnvHashMap::<specs::Entity,Resolution>::default();
+        physic_world.retain_dynamic(|entity| dynamics.get(entity).is_some());
+
+        // positions at the start of the update so the order of bodies doesn't matter
+        let fields = (&fields, &states, &entities).iter()
+            .map(|(field, state, entity)| (entity, state.pos, field.clone()))
+            .collect::<Vec<_>>();
+
+        for (_, forces, state, typ, entity) in (&dynamics, &mut forces, &states, &types, &entities).iter() {
+            for &(field_entity, center, ref field) in &fields {
+                if field_entity == entity || typ.group & field.mask == 0 || typ.mask & field.group == 0 {
+                    continue;
+                }
+                forces.add_transient(field_force(center, state.pos, field.strength, field.radius, field.falloff));
+            }
+        }
+
+        // ordered by entity so every peer resolves collisions in the same order
+        let mut resolutions = BTreeMap::<specs::Entity,Resolution>::new();
 
         for (_, state, typ, entity) in (&dynamics, &mut states, &types, &entities).iter() {
             let mut f = [0., 0.];
@@ -35,9 +57,10 @@ impl specs::System<::utils::UpdateContext> for PhysicSystem {
                 f[0] -= damping*state.vel[0];
                 f[1] -= damping*state.vel[1];
             }
-            if let Some(force) = forces.get(entity) {
-                f[0] += force.coef*force.strength*force.angle.cos();
-                f[1] += force.coef*force.strength*force.angle.sin();
+            if let Some(forces) = forces.get(entity) {
+                let total = forces.total();
+                f[0] += total[0];
+                f[1] += total[1];
             }
 
             state.acc[0] = f[0]/typ.weight;
@@ -46,14 +69,56 @@ impl specs::System<::utils::UpdateContext> for PhysicSystem {
             state.vel[0] += dt*state.acc[0];
             state.vel[1] += dt*state.acc[1];
 
+            if let Some(forces) = forces.get(entity) {
+                state.vel[0] += forces.impulse[0]/typ.weight;
+                state.vel[1] += forces.impulse[1]/typ.weight;
+            }
+
+            let previous_pos = state.pos;
+
             state.pos[0] += dt*state.vel[0];
             state.pos[1] += dt*state.vel[1];
 
+            let mut torque = 0.;
+
+            if let Some(&PhysicAngularDamping(damping)) = angular_dampings.get(entity) {
+                torque -= damping*state.ang_vel;
+            }
+            if let Some(t) = torques.get(entity) {
+                torque += t.coef*t.strength;
+            }
+
+            state.ang_acc = torque/typ.weight;
+            state.ang_vel += dt*state.ang_acc;
+            state.angle += dt*state.ang_vel;
+
+            if bullets.get(entity).is_some() && typ.mask != 0 {
+                let sweep = ShapeCast {
+                    pos: previous_pos,
+                    shape: typ.shape.rotated(state.angle),
+                    mask: typ.mask,
+                    group: typ.group,
+                    not: vec!(entity),
+                };
+                if let Some(toi) = physic_world.sweep(&sweep, state.pos) {
+                    state.pos[0] = previous_pos[0] + toi*(state.pos[0] - previous_pos[0]);
+                    state.pos[1] = previous_pos[1] + toi*(state.pos[1] - previous_pos[1]);
+                }
+            }
+
+            physic_world.insert_dynamic(EntityInformation {
+                entity: entity,
+                pos: state.pos,
+                group: typ.group,
+                shape: typ.shape.rotated(state.angle),
+                mask: typ.mask,
+            });
+
             if typ.mask == 0 { continue }
 
             let shape_cast = ShapeCast {
                 pos: state.pos,
-                shape: typ.shape.clone(),
+                shape: typ.shape.rotated(state.angle),
                 mask: typ.mask,
                 group: typ.group,
                 not: vec!(entity),
@@ -61,6 +126,11 @@ impl specs::System<::utils::UpdateContext> for PhysicSystem {
 
             physic_world.apply_on_shape(&shape_cast, &mut |other_info,collision| {
                 let other_type = types.get(other_info.entity).expect("physic entity expect type component");
+
+                let sensor = typ.sensor || other_type.sensor;
+                collision_events.insert(entity, other_info.entity, collision, sensor);
+                if sensor { return }
+
                 let rate = match (typ.weight, other_type.weight) {
                      (f32::MAX, f32::MAX) => 0.5,
                     (f32::MAX, _) => 1.,
@@ -83,14 +153,10 @@ impl specs::System<::utils::UpdateContext> for PhysicSystem {
                     resolutions.entry(other_info.entity).or_insert(Resolution::none()).push(resolution);
                 }
             });
+        }
 
-            physic_world.insert_dynamic(EntityInformation {
-                entity: entity,
-                pos: state.pos,
-                group: typ.group,
-                shape: typ.shape.clone(),
-                mask: typ.mask,
-            });
+        for forces in (&mut forces).iter() {
+            forces.clear_transient();
         }
 
         for (entity,res) in resolutions {
@@ -102,9 +168,7 @@ impl specs::System<::utils::UpdateContext> for PhysicSystem {
 
             match typ.collision {
                 CollisionBehavior::Bounce => {
-                    let angle = state.vel[1].atan2(state.vel[0]) + PI;
-                    state.vel[0] = angle.cos();
-                    state.vel[1] = angle.sin();
+                    state.vel = bounce_velocity(state.vel, [res.dx, res.dy], typ.restitution, typ.friction);
                 },
                 CollisionBehavior::Stop => state.vel = [0.,0.],
                 CollisionBehavior::Back => {
@@ -113,17 +177,35 @@ impl specs::System<::utils::UpdateContext> for PhysicSystem {
                 },
                 CollisionBehavior::Persist => (),
             }
+
+            if dynamics.get(entity).is_some() {
+                physic_world.insert_dynamic(EntityInformation {
+                    entity: entity,
+                    pos: state.pos,
+                    group: typ.group,
+                    shape: typ.shape.rotated(state.angle),
+                    mask: typ.mask,
+                });
+            }
         }
 
-        physic_world.movable = FnvHashMap::default(); // TODO only rewrite those that have been resolved
-        for (_,state,typ,entity) in (&dynamics, &mut states, &types, &entities).iter() {
-            physic_world.insert_dynamic(EntityInformation {
-                entity: entity,
-                pos: state.pos,
-                group: typ.group,
-                shape: typ.shape.clone(),
-                mask: typ.mask,
-            });
+        if let Some(quantum) = stepping.quantum {
+            for (_, state, typ, entity) in (&dynamics, &mut states, &types, &entities).iter() {
+                state.pos = [quantize(state.pos[0], quantum), quantize(state.pos[1], quantum)];
+                state.vel = [quantize(state.vel[0], quantum), quantize(state.vel[1], quantum)];
+                state.angle = quantize(state.angle, quantum);
+                state.ang_vel = quantize(state.ang_vel, quantum);
+
+                physic_world.insert_dynamic(EntityInformation {
+                    entity: entity,
+                    pos: state.pos,
+                    group: typ.group,
+                    shape: typ.shape.rotated(state.angle),
+                    mask: typ.mask,
+                });
+            }
         }
+
+        collision_events.flush();
     }
 }
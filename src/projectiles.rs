@@ -0,0 +1,264 @@
+//! Projectiles moved by the physic
+//!
+//! A projectile is a physic bullet: it is swept along its move so it can't
+//! go through thin walls. On its first contact with a body other than its
+//! owner the impact callback is called and its impact is triggered:
+//!
+//! * destroy: the body is damaged and the projectile removed
+//! * bounce: the body is damaged and the projectile bounces off it
+//! * explode: the projectile is removed and an explosion of its damage is
+//!   pushed at its position, the body is only damaged by the blast;
+//!   it also explodes at the end of its lifetime,
+//!   radius and impulse of the blast are in ProjectileExplosion
+//!
+//! Projectiles with other impacts are simply removed at the end of their lifetime.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Impact {
+    Destroy,
+    Bounce,
+    Explode,
+}
+
+impl Impact {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Impact::Destroy => "destroy",
+            Impact::Bounce => "bounce",
+            Impact::Explode => "explode",
+        }
+    }
+    pub fn from_str(s: &str) -> Option<Impact> {
+        match s {
+            "destroy" => Some(Impact::Destroy),
+            "bounce" => Some(Impact::Bounce),
+            "explode" => Some(Impact::Explode),
+            _ => None,
+        }
+    }
+}
+
+pub mod components {
+    use specs;
+    use super::Impact;
+
+    impl_component! {
+        Projectile: HashMapStorage,
+    }
+
+    #[derive(Clone)]
+    pub struct Projectile {
+        /// never hit by the projectile
+        pub owner: Option<specs::Entity>,
+        /// time left before removal
+        pub lifetime: f32,
+        pub impact: Impact,
+        pub damage: f32,
+    }
+}
+
+pub mod update_systems {
+    use specs::{self, Join};
+    use fnv::FnvHashSet;
+    use components::*;
+    use physics::ContactState;
+    use resources::{CollisionEvents, Damage, Damages, Explosion, Explosions, LuaCallbacks, PhysicWorld, ProjectileExplosion};
+    use health::DamageKind;
+
+    use super::Impact;
+
+    pub struct ProjectileSystem;
+    impl specs::System<::utils::UpdateContext> for ProjectileSystem {
+        fn run(&mut self, arg: specs::RunArg, context: ::utils::UpdateContext) {
+            let (mut projectiles, states, healths, collision_events, projectile_explosion, mut explosions, mut damages, mut physic_world, mut callbacks, entities) = arg.fetch(|world| {
+                (
+                    world.write::<Projectile>(),
+                    world.read::<PhysicState>(),
+                    world.read::<Health>(),
+                    world.read_resource::<CollisionEvents>(),
+                    world.read_resource::<ProjectileExplosion>(),
+                    world.write_resource::<Explosions>(),
+                    world.write_resource::<Damages>(),
+                    world.write_resource::<PhysicWorld>(),
+                    world.write_resource::<LuaCallbacks>(),
+                    world.entities(),
+                )
+            });
+
+            let mut removed = FnvHashSet::default();
+
+            for event in &collision_events.events {
+                if event.state != ContactState::Started || event.sensor {
+                    continue;
+                }
+                for &(entity, other) in &[(event.a, event.b), (event.b, event.a)] {
+                    if removed.contains(&entity) {
+                        continue;
+                    }
+                    let projectile = match projectiles.get(entity) {
+                        Some(projectile) => projectile,
+                        None => continue,
+                    };
+                    if projectile.owner == Some(other) {
+                        continue;
+                    }
+
+                    let pos = states.get(entity).unwrap().pos;
+                    callbacks.0.push(format!("on_impact({},{},{},{})", entity.get_id(), other.get_id(), pos[0], pos[1]));
+
+                    if projectile.impact != Impact::Explode && healths.get(other).is_some() {
+                        damages.0.push(Damage {
+                            target: other,
+                            source: projectile.owner,
+                            amount: projectile.damage,
+                            kind: DamageKind::Bullet,
+                        });
+                    }
+                    if projectile.impact != Impact::Bounce {
+                        removed.insert(entity);
+                    }
+                }
+            }
+
+            for (projectile, entity) in (&mut projectiles, &entities).iter() {
+                projectile.lifetime -= context.dt;
+                if projectile.lifetime <= 0. {
+                    removed.insert(entity);
+                }
+            }
+
+            // sorted so that explosions are applied in the same order on every peer
+            let mut removed = removed.into_iter().collect::<Vec<_>>();
+            removed.sort();
+            for entity in removed {
+                let projectile = projectiles.get(entity).unwrap();
+                if projectile.impact == Impact::Explode {
+                    explosions.0.push(Explosion {
+                        pos: states.get(entity).unwrap().pos,
+                        radius: projectile_explosion.radius,
+                        damage: projectile.damage,
+                        impulse: projectile_explosion.impulse,
+                        source: projectile.owner,
+                    });
+                }
+                physic_world.remove_dynamic(entity);
+                arg.delete(entity);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn test_planner() -> specs::Planner<::utils::UpdateContext> {
+        let mut world = specs::World::new();
+        ::resources::add_resources(&mut world);
+        register_components(&mut world);
+        let mut planner = specs::Planner::new(world, 1);
+        planner.add_system(ProjectileSystem, "projectile", 0);
+        planner
+    }
+
+    /// Update with the contacts of the physic update, sensor if true
+    #[cfg(test)]
+    fn step(planner: &mut specs::Planner<::utils::UpdateContext>, contacts: &[(specs::Entity, specs::Entity, bool)]) {
+        use physics::Collision;
+        {
+            let mut collision_events = planner.mut_world().write_resource::<CollisionEvents>();
+            for &(a, b, sensor) in contacts {
+                collision_events.insert(a, b, &Collision { delta_x: 0.1, delta_y: 0. }, sensor);
+            }
+            collision_events.flush();
+        }
+        planner.dispatch(::utils::UpdateContext { dt: 0.1 });
+        planner.wait();
+        planner.mut_world().maintain();
+    }
+
+    #[cfg(test)]
+    fn add_projectile(world: &mut specs::World, owner: Option<specs::Entity>, lifetime: f32, impact: Impact) -> specs::Entity {
+        world.create_now()
+            .with(PhysicState::new([0., 0.]))
+            .with(Projectile {
+                owner: owner,
+                lifetime: lifetime,
+                impact: impact,
+                damage: 3.,
+            })
+            .build()
+    }
+
+    #[test]
+    fn destroy_test() {
+        let mut planner = test_planner();
+        let (owner, sensor, target, projectile) = {
+            let world = planner.mut_world();
+            let owner = world.create_now().with(Health::new(10.)).build();
+            let sensor = world.create_now().with(Health::new(10.)).build();
+            let target = world.create_now().with(Health::new(10.)).build();
+            (owner, sensor, target, add_projectile(world, Some(owner), 10., Impact::Destroy))
+        };
+
+        step(&mut planner, &[(projectile, owner, false), (projectile, sensor, true)]);
+        assert!(planner.mut_world().is_alive(projectile));
+        assert!(planner.mut_world().read_resource::<Damages>().0.is_empty());
+
+        step(&mut planner, &[(projectile, owner, false), (projectile, target, false)]);
+        let world = planner.mut_world();
+        assert!(!world.is_alive(projectile));
+        let damages = world.read_resource::<Damages>();
+        assert_eq!(damages.0.len(), 1);
+        assert!(damages.0[0].target == target && damages.0[0].source == Some(owner) && damages.0[0].amount == 3.);
+        assert_eq!(world.read_resource::<LuaCallbacks>().0, vec!(
+            format!("on_impact({},{},0,0)", projectile.get_id(), target.get_id()),
+        ));
+    }
+
+    #[test]
+    fn bounce_test() {
+        let mut planner = test_planner();
+        let (target, projectile) = {
+            let world = planner.mut_world();
+            let target = world.create_now().with(Health::new(10.)).build();
+            (target, add_projectile(world, None, 10., Impact::Bounce))
+        };
+
+        // only the start of the contact hits
+        step(&mut planner, &[(projectile, target, false)]);
+        step(&mut planner, &[(projectile, target, false)]);
+        let world = planner.mut_world();
+        assert!(world.is_alive(projectile));
+        assert_eq!(world.read_resource::<Damages>().0.len(), 1);
+        assert!(world.read_resource::<Explosions>().0.is_empty());
+    }
+
+    #[test]
+    fn explode_test() {
+        let mut planner = test_planner();
+        let (owner, target, projectile) = {
+            let world = planner.mut_world();
+            let owner = world.create_now().build();
+            let target = world.create_now().with(Health::new(10.)).build();
+            (owner, target, add_projectile(world, Some(owner), 10., Impact::Explode))
+        };
+
+        step(&mut planner, &[(projectile, target, false)]);
+        let world = planner.mut_world();
+        assert!(!world.is_alive(projectile));
+        // damages are only applied by the blast
+        assert!(world.read_resource::<Damages>().0.is_empty());
+        let explosions = world.read_resource::<Explosions>();
+        assert_eq!(explosions.0.len(), 1);
+        assert!(explosions.0[0].source == Some(owner) && explosions.0[0].damage == 3.);
+    }
+
+    #[test]
+    fn lifetime_test() {
+        let mut planner = test_planner();
+        let projectile = add_projectile(planner.mut_world(), None, 0.15, Impact::Destroy);
+
+        step(&mut planner, &[]);
+        assert!(planner.mut_world().is_alive(projectile));
+        step(&mut planner, &[]);
+        assert!(!planner.mut_world().is_alive(projectile));
+        assert!(planner.mut_world().read_resource::<Damages>().0.is_empty());
+    }
+}
@@ -1,11 +1,74 @@
 pub use ::physics::resources::*;
 pub use ::notifications::resources::*;
+pub use ::director::resources::*;
+pub use ::weapons::resources::*;
+pub use ::health::resources::*;
+pub use ::explosions::resources::*;
+pub use ::doors::resources::*;
 
 pub fn add_resources(world: &mut ::specs::World) {
     ::physics::resources::add_resources(world);
     ::notifications::resources::add_resources(world);
+    ::director::resources::add_resources(world);
+    ::weapons::resources::add_resources(world);
+    ::health::resources::add_resources(world);
+    ::explosions::resources::add_resources(world);
+    ::doors::resources::add_resources(world);
     world.add_resource(Cursor::new());
     world.add_resource(Zoom::new());
+    world.add_resource(LuaCallbacks::new());
+    world.add_resource(Level::new());
+    world.add_resource(SpawnPoints::new());
+    world.add_resource(EntitySpawns::new());
+    world.add_resource(NextPlayer::new());
+}
+
+/// Metadata of the last loaded level
+pub struct Level {
+    pub name: String,
+    pub author: String,
+    pub description: String,
+}
+impl Level {
+    pub fn new() -> Self {
+        Level {
+            name: String::new(),
+            author: String::new(),
+            description: String::new(),
+        }
+    }
+}
+
+/// Spawn points of the loaded levels
+pub struct SpawnPoints(pub Vec<[f32; 2]>);
+impl SpawnPoints {
+    pub fn new() -> Self {
+        SpawnPoints(Vec::new())
+    }
+}
+
+/// Player of the next character: ids of players are never reused
+pub struct NextPlayer(pub u32);
+impl NextPlayer {
+    pub fn new() -> Self {
+        NextPlayer(0)
+    }
+}
+
+/// Entities to build after the update: systems can't use the entity builders
+pub struct EntitySpawns(pub Vec<::entities::EntityBuilderMsg>);
+impl EntitySpawns {
+    pub fn new() -> Self {
+        EntitySpawns(Vec::new())
+    }
+}
+
+/// Lua commands to execute after the update, see api_callee
+pub struct LuaCallbacks(pub Vec<String>);
+impl LuaCallbacks {
+    pub fn new() -> Self {
+        LuaCallbacks(Vec::new())
+    }
 }
 
 pub struct Zoom(pub f32);
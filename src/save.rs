@@ -0,0 +1,735 @@
+//! Save and load the world to a versioned text file
+//!
+//! The first line is `ruga-world VERSION`, then each line is a keyword
+//! followed by values separated by spaces, strings are quoted.
+//! Components follow the `entity` line of their entity:
+//!
+//! ```text
+//! ruga-world 2
+//! ticks 120
+//! zoom 0.05
+//! next_player 1
+//! director false true 0 build_up 0 0 0 false 0 1 "stress_per_hit" 0.2
+//! notification 600 "hello world"
+//! entity 0
+//! physic_state 10 10 0 0 0 0 0 0 0
+//! physic_static
+//! ```
+//!
+//! Loaded entities get new ids, references to other entities like
+//! `projectile_owner` are saved as the id of their `entity` line and remapped.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use specs::{self, Join};
+use physics::{Shape, CollisionBehavior, Falloff};
+use monsters::ZombieState;
+use weapons::{Kind, State};
+use projectiles::Impact;
+use doors::DoorState;
+use components::*;
+use resources::{CollisionEvents, Damages, Director, DirectorParameters, DirectorPhase, DoorRequests, EntitySpawns};
+use resources::{Explosions, FireEvents, Level, NextPlayer, Notifications, PhysicWorld, Shockwaves, SpawnPoints, Tracers, Zoom};
+
+const VERSION: u32 = 2;
+
+/// Split the line in words separated by whitespaces,
+/// a word can be a string between quotes with `\"`, `\\` and `\n` escaped
+pub fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec!();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        match chars.peek() {
+            None => break,
+            Some(&'"') => {
+                chars.next();
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some(c @ '"') | Some(c @ '\\') => word.push(c),
+                            _ => return Err("invalid escape in string".into()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated string".into()),
+                    }
+                }
+                words.push(word);
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while chars.peek().map_or(false, |c| !c.is_whitespace()) {
+                    word.push(chars.next().unwrap());
+                }
+                words.push(word);
+            }
+        }
+    }
+    Ok(words)
+}
+
+/// Inverse of split_words for one word
+pub fn quote(word: &str) -> String {
+    let escaped = word.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Values of a line
+pub struct Words(::std::vec::IntoIter<String>);
+impl Words {
+    pub fn new(words: Vec<String>) -> Self {
+        Words(words.into_iter())
+    }
+    pub fn next(&mut self) -> Result<String, String> {
+        self.0.next().ok_or("missing value".into())
+    }
+    fn parse<T: FromStr>(&mut self, name: &str) -> Result<T, String> {
+        let word = self.next()?;
+        T::from_str(&*word).map_err(|_| format!("invalid {}: '{}'", name, word))
+    }
+    /// Error if some values are left
+    pub fn end(mut self) -> Result<(), String> {
+        match self.0.next() {
+            Some(word) => Err(format!("unexpected value: '{}'", word)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub trait Save: Sized {
+    fn save(&self, words: &mut Vec<String>);
+    fn load(words: &mut Words) -> Result<Self, String>;
+}
+
+macro_rules! impl_save_from_str {
+    ($($typ:ident),*) => {
+        $(impl Save for $typ {
+            fn save(&self, words: &mut Vec<String>) {
+                words.push(format!("{}", self));
+            }
+            fn load(words: &mut Words) -> Result<Self, String> {
+                words.parse(stringify!($typ))
+            }
+        })*
+    }
+}
+
+macro_rules! impl_save_struct {
+    ($typ:ident { $($field:ident),* }) => {
+        impl Save for $typ {
+            fn save(&self, words: &mut Vec<String>) {
+                $(self.$field.save(words);)*
+            }
+            fn load(words: &mut Words) -> Result<Self, String> {
+                Ok($typ {
+                    $($field: Save::load(words)?,)*
+                })
+            }
+        }
+    }
+}
+
+macro_rules! impl_save_newtype {
+    ($($typ:ident),*) => {
+        $(impl Save for $typ {
+            fn save(&self, words: &mut Vec<String>) {
+                self.0.save(words);
+            }
+            fn load(words: &mut Words) -> Result<Self, String> {
+                Ok($typ(Save::load(words)?))
+            }
+        })*
+    }
+}
+
+macro_rules! impl_save_unit {
+    ($($typ:ident),*) => {
+        $(impl Save for $typ {
+            fn save(&self, _words: &mut Vec<String>) {}
+            fn load(_words: &mut Words) -> Result<Self, String> {
+                Ok($typ)
+            }
+        })*
+    }
+}
+
+impl_save_from_str!(f32, u32, u64, usize, bool);
+
+impl Save for String {
+    fn save(&self, words: &mut Vec<String>) {
+        words.push(quote(self));
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        words.next()
+    }
+}
+
+impl Save for [f32; 2] {
+    fn save(&self, words: &mut Vec<String>) {
+        self[0].save(words);
+        self[1].save(words);
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        Ok([Save::load(words)?, Save::load(words)?])
+    }
+}
+
+impl Save for [f32; 4] {
+    fn save(&self, words: &mut Vec<String>) {
+        for value in self {
+            value.save(words);
+        }
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        Ok([Save::load(words)?, Save::load(words)?, Save::load(words)?, Save::load(words)?])
+    }
+}
+
+impl<A: Save, B: Save> Save for (A, B) {
+    fn save(&self, words: &mut Vec<String>) {
+        self.0.save(words);
+        self.1.save(words);
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        Ok((Save::load(words)?, Save::load(words)?))
+    }
+}
+
+impl<T: Save> Save for Option<T> {
+    fn save(&self, words: &mut Vec<String>) {
+        match *self {
+            Some(ref value) => {
+                words.push("some".into());
+                value.save(words);
+            }
+            None => words.push("none".into()),
+        }
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        match &*words.next()? {
+            "some" => Ok(Some(Save::load(words)?)),
+            "none" => Ok(None),
+            word => Err(format!("invalid option: '{}'", word)),
+        }
+    }
+}
+
+impl<T: Save> Save for Vec<T> {
+    fn save(&self, words: &mut Vec<String>) {
+        self.len().save(words);
+        for value in self {
+            value.save(words);
+        }
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        let len: usize = Save::load(words)?;
+        let mut vec = Vec::new();
+        for _ in 0..len {
+            vec.push(Save::load(words)?);
+        }
+        Ok(vec)
+    }
+}
+
+impl Save for Shape {
+    fn save(&self, words: &mut Vec<String>) {
+        match *self {
+            Shape::Circle(radius) => {
+                words.push("circle".into());
+                radius.save(words);
+            }
+            Shape::Rectangle(width, height) => {
+                words.push("rectangle".into());
+                width.save(words);
+                height.save(words);
+            }
+            Shape::Polygon(ref vertices, angle) => {
+                words.push("polygon".into());
+                vertices.save(words);
+                angle.save(words);
+            }
+            Shape::Segment(length, angle) => {
+                words.push("segment".into());
+                length.save(words);
+                angle.save(words);
+            }
+        }
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        match &*words.next()? {
+            "circle" => Ok(Shape::Circle(Save::load(words)?)),
+            "rectangle" => Ok(Shape::Rectangle(Save::load(words)?, Save::load(words)?)),
+            "polygon" => Ok(Shape::Polygon(Save::load(words)?, Save::load(words)?)),
+            "segment" => Ok(Shape::Segment(Save::load(words)?, Save::load(words)?)),
+            word => Err(format!("invalid shape: '{}'", word)),
+        }
+    }
+}
+
+impl Save for CollisionBehavior {
+    fn save(&self, words: &mut Vec<String>) {
+        words.push(match *self {
+            CollisionBehavior::Bounce => "bounce",
+            CollisionBehavior::Back => "back",
+            CollisionBehavior::Persist => "persist",
+            CollisionBehavior::Stop => "stop",
+        }.into());
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        match &*words.next()? {
+            "bounce" => Ok(CollisionBehavior::Bounce),
+            "back" => Ok(CollisionBehavior::Back),
+            "persist" => Ok(CollisionBehavior::Persist),
+            "stop" => Ok(CollisionBehavior::Stop),
+            word => Err(format!("invalid collision behavior: '{}'", word)),
+        }
+    }
+}
+
+impl Save for Falloff {
+    fn save(&self, words: &mut Vec<String>) {
+        words.push(self.name().into());
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        let word = words.next()?;
+        Falloff::from_str(&*word).ok_or(format!("invalid falloff: '{}'", word))
+    }
+}
+
+impl Save for ZombieState {
+    fn save(&self, words: &mut Vec<String>) {
+        words.push(match *self {
+            ZombieState::Idle => "idle",
+            ZombieState::Walk => "walk",
+            ZombieState::Run => "run",
+        }.into());
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        match &*words.next()? {
+            "idle" => Ok(ZombieState::Idle),
+            "walk" => Ok(ZombieState::Walk),
+            "run" => Ok(ZombieState::Run),
+            word => Err(format!("invalid zombie state: '{}'", word)),
+        }
+    }
+}
+
+impl Save for DirectorPhase {
+    fn save(&self, words: &mut Vec<String>) {
+        words.push(self.name().into());
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        let word = words.next()?;
+        DirectorPhase::from_str(&*word).ok_or(format!("invalid director phase: '{}'", word))
+    }
+}
+
+/// Saved by name so that files stay valid when parameters are added
+impl Save for DirectorParameters {
+    fn save(&self, words: &mut Vec<String>) {
+        self.values().save(words);
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        let mut parameters = DirectorParameters::new();
+        let values: Vec<(String, f32)> = Save::load(words)?;
+        for (name, value) in values {
+            parameters.set(&*name, value)?;
+        }
+        Ok(parameters)
+    }
+}
+
+impl Save for Kind {
+    fn save(&self, words: &mut Vec<String>) {
+        words.push(self.name().into());
+        if let Kind::Hammer(side) = *self {
+            side.save(words);
+        }
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        let word = words.next()?;
+        match Kind::from_str(&*word) {
+            Some(Kind::Hammer(_)) => Ok(Kind::Hammer(Save::load(words)?)),
+            Some(kind) => Ok(kind),
+            None => Err(format!("invalid weapon kind: '{}'", word)),
+        }
+    }
+}
+
+impl Save for State {
+    fn save(&self, words: &mut Vec<String>) {
+        match *self {
+            State::Reload(t) => {
+                words.push("reload".into());
+                t.save(words);
+            }
+            State::Ready => words.push("ready".into()),
+            State::Setup(t) => {
+                words.push("setup".into());
+                t.save(words);
+            }
+            State::Setdown(t) => {
+                words.push("setdown".into());
+                t.save(words);
+            }
+        }
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        match &*words.next()? {
+            "reload" => Ok(State::Reload(Save::load(words)?)),
+            "ready" => Ok(State::Ready),
+            "setup" => Ok(State::Setup(Save::load(words)?)),
+            "setdown" => Ok(State::Setdown(Save::load(words)?)),
+            word => Err(format!("invalid weapon state: '{}'", word)),
+        }
+    }
+}
+
+impl Save for Impact {
+    fn save(&self, words: &mut Vec<String>) {
+        words.push(self.name().into());
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        let word = words.next()?;
+        Impact::from_str(&*word).ok_or(format!("invalid impact: '{}'", word))
+    }
+}
+
+impl Save for DoorState {
+    fn save(&self, words: &mut Vec<String>) {
+        words.push(self.name().into());
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        let word = words.next()?;
+        DoorState::from_str(&*word).ok_or(format!("invalid door state: '{}'", word))
+    }
+}
+
+/// The owner is saved on its own `projectile_owner` line
+impl Save for Projectile {
+    fn save(&self, words: &mut Vec<String>) {
+        self.lifetime.save(words);
+        self.impact.save(words);
+        self.damage.save(words);
+    }
+    fn load(words: &mut Words) -> Result<Self, String> {
+        Ok(Projectile {
+            owner: None,
+            lifetime: Save::load(words)?,
+            impact: Save::load(words)?,
+            damage: Save::load(words)?,
+        })
+    }
+}
+
+impl_save_struct!(PhysicState { pos, vel, acc, angle, ang_vel, ang_acc });
+impl_save_struct!(PhysicType { shape, collision, weight, group, mask, sensor, restitution, friction });
+impl_save_struct!(Force { angle, strength, coef });
+impl_save_struct!(PhysicForces { named, transient, impulse });
+impl_save_struct!(PhysicTorque { strength, coef });
+impl_save_struct!(PhysicField { strength, radius, falloff, group, mask });
+impl_save_struct!(DrawPhysic { border, color });
+impl_save_struct!(Zombie { state, alert, walk_force, run_force, vision, alert_time, decay_time });
+impl_save_struct!(Spider { heading, velocity, sight, lifetime, population, openings });
+impl_save_struct!(SpiderSpawner { rate, timer, remaining, direction, velocity, sight, lifetime, population });
+impl_save_struct!(Director { enabled, auto_spawn, stress, phase, phase_time, calm, spawn_timer, pending_pickup, decisions, parameters });
+impl_save_struct!(Weapon { reload_factor, setup_factor, setdown_factor, state, kind, shots });
+impl_save_struct!(Health { current, max });
+impl_save_struct!(Armor { points, bullet, explosion, melee });
+impl_save_struct!(Door { state, key });
+impl_save_newtype!(PhysicDamping, PhysicAngularDamping, PlayerControl, Aim, NextWeapon, Pickup, Keys);
+impl_save_unit!(PhysicDynamic, PhysicStatic, PhysicBullet, Shoot, CollisionCallback);
+
+fn line<T: Save>(keyword: &str, value: &T) -> String {
+    let mut words = vec!(keyword.into());
+    value.save(&mut words);
+    words.join(" ")
+}
+
+macro_rules! save {
+    ($($field:ident: $typ:ident,)*) => {
+        fn save_entities(world: &specs::World, lines: &mut Vec<String>) {
+            $(let $field = world.read::<$typ>();)*
+            for entity in (&world.entities()).iter() {
+                lines.push(format!("entity {}", entity.get_id()));
+                $(if let Some(component) = $field.get(entity) {
+                    lines.push(line(stringify!($field), component));
+                })*
+                save_references(world, entity, lines);
+            }
+        }
+
+        #[derive(Default)]
+        struct SavedEntity {
+            id: u32,
+            projectile_owner: Option<u32>,
+            $($field: Option<$typ>,)*
+        }
+        impl SavedEntity {
+            /// Return false if keyword isn't a component
+            fn load_component(&mut self, keyword: &str, words: &mut Words) -> Result<bool, String> {
+                $(if keyword == stringify!($field) {
+                    if self.$field.is_some() {
+                        return Err(format!("duplicate component {}", keyword));
+                    }
+                    self.$field = Some(Save::load(words)?);
+                    return Ok(true);
+                })*
+                Ok(false)
+            }
+            fn build(self, world: &mut specs::World) -> specs::Entity {
+                let entity = world.create_now().build();
+                $(if let Some(component) = self.$field {
+                    world.write::<$typ>().insert(entity, component);
+                })*
+                entity
+            }
+        }
+    }
+}
+
+game_components!(save);
+
+/// Save the references of the entity to other entities that are alive
+fn save_references(world: &specs::World, entity: specs::Entity, lines: &mut Vec<String>) {
+    let owner = world.read::<Projectile>().get(entity).and_then(|projectile| projectile.owner);
+    if let Some(owner) = owner.filter(|&owner| world.is_alive(owner)) {
+        lines.push(line("projectile_owner", &owner.get_id()));
+    }
+}
+
+/// Write the world to the file
+pub fn save_world(world: &specs::World, ticks: u64, path: &str) -> Result<(), String> {
+    let mut lines = vec!(format!("ruga-world {}", VERSION));
+    lines.push(line("ticks", &ticks));
+    lines.push(line("zoom", &world.read_resource::<Zoom>().0));
+    lines.push(line("next_player", &world.read_resource::<NextPlayer>().0));
+    lines.push(line("director", &*world.read_resource::<Director>()));
+    for &(ref notification, count) in &world.read_resource::<Notifications>().0 {
+        lines.push(line("notification", &(count, notification.clone())));
+    }
+    save_entities(world, &mut lines);
+    lines.push(String::new());
+
+    let mut file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+    file.write_all(lines.join("\n").as_bytes()).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Replace the world by the one in the file and return its number of ticks,
+/// the world is unchanged on error
+pub fn load_world(world: &mut specs::World, path: &str) -> Result<u64, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut lines = text.lines().enumerate();
+    match lines.next().map(|(_, header)| split_words(header)) {
+        Some(Ok(ref header)) if header.len() == 2 && header[0] == "ruga-world" => {
+            if header[1] != VERSION.to_string() {
+                return Err(format!("{}:1: unsupported version {}", path, header[1]));
+            }
+        }
+        _ => return Err(format!("{}:1: not a world file", path)),
+    }
+
+    let mut ticks = 0;
+    let mut zoom = None;
+    let mut next_player = None;
+    let mut director = None;
+    let mut notifications = vec!();
+    let mut entities = vec!();
+    let mut owners = vec!();
+    for (index, text_line) in lines {
+        let error = |e: String| format!("{}:{}: {}", path, index + 1, e);
+        let mut words = split_words(text_line).map_err(&error)?.into_iter();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let mut words = Words::new(words.collect());
+        match &*keyword {
+            "ticks" => ticks = Save::load(&mut words).map_err(&error)?,
+            "zoom" => zoom = Some(Save::load(&mut words).map_err(&error)?),
+            "next_player" => next_player = Some(Save::load(&mut words).map_err(&error)?),
+            "director" => director = Some(Save::load(&mut words).map_err(&error)?),
+            "notification" => {
+                let (count, notification): (usize, String) = Save::load(&mut words).map_err(&error)?;
+                notifications.push((notification, count));
+            }
+            "entity" => {
+                let id: u32 = Save::load(&mut words).map_err(&error)?;
+                if entities.iter().any(|entity: &SavedEntity| entity.id == id) {
+                    return Err(error(format!("duplicate entity {}", id)));
+                }
+                entities.push(SavedEntity { id: id, ..SavedEntity::default() });
+            }
+            "projectile_owner" => {
+                let owner: u32 = Save::load(&mut words).map_err(&error)?;
+                match entities.last_mut() {
+                    Some(entity) if entity.projectile_owner.is_none() => entity.projectile_owner = Some(owner),
+                    Some(_) => return Err(error("duplicate projectile_owner".into())),
+                    None => return Err(error("projectile_owner outside of an entity".into())),
+                }
+                owners.push((index, owner));
+            }
+            _ => {
+                let known = match entities.last_mut() {
+                    Some(entity) => entity.load_component(&*keyword, &mut words).map_err(&error)?,
+                    None => false,
+                };
+                if !known {
+                    return Err(error(format!("unknown keyword '{}'", keyword)));
+                }
+            }
+        }
+        words.end().map_err(&error)?;
+    }
+
+    for (index, owner) in owners {
+        if !entities.iter().any(|entity| entity.id == owner) {
+            return Err(format!("{}:{}: unknown entity {}", path, index + 1, owner));
+        }
+    }
+
+    let alive = (&world.entities()).iter().collect::<Vec<_>>();
+    for entity in alive {
+        world.delete_now(entity);
+    }
+    let mut built = HashMap::new();
+    let mut references = vec!();
+    for entity in entities {
+        let (id, owner) = (entity.id, entity.projectile_owner);
+        let new = entity.build(world);
+        built.insert(id, new);
+        if let Some(owner) = owner {
+            references.push((new, owner));
+        }
+    }
+    {
+        let mut projectiles = world.write::<Projectile>();
+        for (entity, owner) in references {
+            if let Some(projectile) = projectiles.get_mut(entity) {
+                projectile.owner = Some(built[&owner]);
+            }
+        }
+    }
+    if let Some(zoom) = zoom {
+        world.write_resource::<Zoom>().0 = zoom;
+    }
+    // older files: after the players of the file
+    let next_player = next_player.unwrap_or_else(|| {
+        (&world.read::<PlayerControl>()).iter().map(|player| player.0 + 1).max().unwrap_or(0)
+    });
+    world.write_resource::<NextPlayer>().0 = next_player;
+    if let Some(director) = director {
+        *world.write_resource::<Director>() = director;
+    }
+    world.write_resource::<Notifications>().0 = notifications;
+    *world.write_resource::<CollisionEvents>() = CollisionEvents::new();
+    *world.write_resource::<Level>() = Level::new();
+    *world.write_resource::<SpawnPoints>() = SpawnPoints::new();
+    *world.write_resource::<Explosions>() = Explosions::new();
+    *world.write_resource::<Shockwaves>() = Shockwaves::new();
+    *world.write_resource::<Tracers>() = Tracers::new();
+    *world.write_resource::<Damages>() = Damages::new();
+    *world.write_resource::<DoorRequests>() = DoorRequests::new();
+    *world.write_resource::<FireEvents>() = FireEvents::new();
+    *world.write_resource::<EntitySpawns>() = EntitySpawns::new();
+    world.write_resource::<PhysicWorld>().fill(world);
+    Ok(ticks)
+}
+
+#[test]
+fn split_words_test() {
+    let word = "a \"quoted\" \\ word\nand a line";
+    let line = format!("keyword 1.5 {} {}", quote(word), quote(""));
+    assert_eq!(split_words(&*line).unwrap(), vec!("keyword", "1.5", word, ""));
+    assert_eq!(split_words("  a\t b  ").unwrap(), vec!("a", "b"));
+    assert_eq!(split_words("a \"b c").unwrap_err(), "unterminated string");
+    assert_eq!(split_words("a \"b\\t\"").unwrap_err(), "invalid escape in string");
+}
+
+#[test]
+fn save_load_test() {
+    use physics::Shape;
+
+    fn strip_ids(text: &str) -> Vec<&str> {
+        text.lines()
+            .filter(|line| !line.starts_with("entity ") && !line.starts_with("projectile_owner "))
+            .collect()
+    }
+
+    let mut world = specs::World::new();
+    ::resources::add_resources(&mut world);
+    register_components(&mut world);
+    let deleted = world.create_now().build();
+    let owner = world.create_now()
+        .with(PhysicState::new([1., 2.]))
+        .with(PhysicType::new_movable(1, 2, Shape::Circle(0.5), CollisionBehavior::Persist, 1.))
+        .with(PhysicDynamic)
+        .with(Health::new(10.))
+        .with(Weapon::new(Kind::Shotgun, 0.1, 0.2, 0.3))
+        .build();
+    world.create_now()
+        .with(PhysicState::new([3., 4.]))
+        .with(Projectile {
+            owner: Some(owner),
+            lifetime: 2.,
+            impact: Impact::Bounce,
+            damage: 5.,
+        })
+        .build();
+    world.create_now()
+        .with(PhysicState::new([5., 6.]))
+        .with(PhysicType::new_static(1, 2, Shape::Rectangle(1., 2.)))
+        .with(PhysicStatic)
+        .with(Door { state: DoorState::Locked, key: "red \"key\"".into() })
+        .build();
+    world.delete_now(deleted);
+    world.write_resource::<Notifications>().0.push(("hello\nworld".into(), 60));
+    world.write_resource::<Zoom>().0 = 0.1;
+
+    let dir = ::std::env::temp_dir();
+    let first = dir.join(format!("ruga-save-test-{}-1", ::std::process::id()));
+    let second = dir.join(format!("ruga-save-test-{}-2", ::std::process::id()));
+    let (first, second) = (first.to_str().unwrap(), second.to_str().unwrap());
+    save_world(&world, 42, first).unwrap();
+
+    let mut loaded = specs::World::new();
+    ::resources::add_resources(&mut loaded);
+    register_components(&mut loaded);
+    loaded.create_now().with(PhysicState::new([7., 7.])).build();
+    loaded.write_resource::<SpawnPoints>().0.push([1., 1.]);
+    loaded.write_resource::<Level>().name = "stale".into();
+    assert_eq!(load_world(&mut loaded, first).unwrap(), 42);
+    save_world(&loaded, 42, second).unwrap();
+
+    let mut texts = vec!();
+    for path in &[first, second] {
+        let mut text = String::new();
+        File::open(path).unwrap().read_to_string(&mut text).unwrap();
+        ::std::fs::remove_file(path).unwrap();
+        texts.push(text);
+    }
+    assert_eq!(strip_ids(&*texts[0]), strip_ids(&*texts[1]));
+    assert_eq!((&loaded.entities()).iter().count(), 3);
+    assert!(loaded.read_resource::<SpawnPoints>().0.is_empty());
+    assert!(loaded.read_resource::<Level>().name.is_empty());
+    assert_eq!(loaded.read_resource::<Notifications>().0, vec!(("hello\nworld".to_string(), 60)));
+
+    let projectiles = loaded.read::<Projectile>();
+    let (projectile, _) = (&projectiles, &loaded.entities()).iter().next().unwrap();
+    let owner = projectile.owner.unwrap();
+    assert!(loaded.is_alive(owner));
+    assert_eq!(loaded.read::<PhysicState>().get(owner).unwrap().pos, [1., 2.]);
+    assert!(loaded.read::<Weapon>().get(owner).is_some());
+}
@@ -0,0 +1,143 @@
+use specs::{self, Join};
+use fnv::{FnvHashMap, FnvHashSet};
+use physics;
+use components::*;
+use resources::{CollisionEvents, Director, NextPlayer, PhysicWorld};
+
+macro_rules! snapshot {
+    ($($field:ident: $typ:ident,)*) => {
+        /// Copy of the state of the world at one tick
+        pub struct Snapshot {
+            pub ticks: u64,
+            pub checksum: u64,
+            entities: Vec<specs::Entity>,
+            collision_events: CollisionEvents,
+            director: Director,
+            next_player: u32,
+            $($field: Vec<(specs::Entity, $typ)>,)*
+        }
+
+        impl Snapshot {
+            pub fn new(world: &specs::World, ticks: u64) -> Self {
+                let entities = world.entities();
+                Snapshot {
+                    ticks: ticks,
+                    checksum: physics::checksum(world),
+                    entities: (&entities).iter().collect(),
+                    collision_events: world.read_resource::<CollisionEvents>().clone(),
+                    director: world.read_resource::<Director>().clone(),
+                    next_player: world.read_resource::<NextPlayer>().0,
+                    $($field: {
+                        let storage = world.read::<$typ>();
+                        (&storage, &entities).iter()
+                            .map(|(component, entity)| (entity, component.clone()))
+                            .collect()
+                    },)*
+                }
+            }
+
+            /// Restore the world in place
+            ///
+            /// entities created since the snapshot are deleted,
+            /// entities deleted since the snapshot are created again with the same id
+            /// so that ids known by Lua, the checksum and the order of entities are
+            /// the same as on a peer that didn't roll back,
+            /// the references to them are updated to their new generation
+            pub fn restore(&self, world: &mut specs::World) {
+                let saved = self.entities.iter().cloned().collect::<FnvHashSet<_>>();
+                let alive = (&world.entities()).iter().collect::<Vec<_>>();
+                for entity in alive {
+                    if !saved.contains(&entity) {
+                        world.delete_now(entity);
+                    }
+                }
+
+                // entities are allocated at the lowest free id: create entities until
+                // all missing ids are allocated and delete the others
+                let missing = self.entities.iter()
+                    .filter(|&&entity| !world.is_alive(entity))
+                    .map(|&entity| (entity.get_id(), entity))
+                    .collect::<FnvHashMap<_, _>>();
+                let mut new_entities = FnvHashMap::default();
+                let mut fillers = vec!();
+                while new_entities.len() < missing.len() {
+                    let entity = world.create_now().build();
+                    match missing.get(&entity.get_id()) {
+                        Some(&old) => { new_entities.insert(old, entity); },
+                        None => fillers.push(entity),
+                    }
+                }
+                for entity in fillers {
+                    world.delete_now(entity);
+                }
+                let map = |entity: specs::Entity| *new_entities.get(&entity).unwrap_or(&entity);
+
+                $({
+                    let mut storage = world.write::<$typ>();
+                    storage.clear();
+                    for &(entity, ref component) in &self.$field {
+                        storage.insert(map(entity), component.clone());
+                    }
+                })*
+
+                // references to entities created again
+                for projectile in (&mut world.write::<Projectile>()).iter() {
+                    projectile.owner = projectile.owner.map(&map);
+                }
+
+                let mut collision_events = self.collision_events.clone();
+                collision_events.map_entities(&map);
+                *world.write_resource::<CollisionEvents>() = collision_events;
+                *world.write_resource::<Director>() = self.director.clone();
+                world.write_resource::<NextPlayer>().0 = self.next_player;
+                world.write_resource::<PhysicWorld>().fill(world);
+            }
+        }
+    }
+}
+
+game_components!(snapshot);
+
+#[test]
+fn restore_deleted_entity_test() {
+    use projectiles::Impact;
+    use physics::Collision;
+
+    let mut world = specs::World::new();
+    ::resources::add_resources(&mut world);
+    register_components(&mut world);
+    let first = world.create_now().with(PhysicState::new([0., 0.])).build();
+    let owner = world.create_now().with(PhysicState::new([1., 0.])).build();
+    let projectile = world.create_now()
+        .with(PhysicState::new([2., 0.]))
+        .with(Projectile {
+            owner: Some(owner),
+            lifetime: 1.,
+            impact: Impact::Destroy,
+            damage: 1.,
+        })
+        .build();
+    {
+        let mut collision_events = world.write_resource::<CollisionEvents>();
+        collision_events.insert(owner, projectile, &Collision { delta_x: 1., delta_y: 0. }, false);
+        collision_events.flush();
+    }
+
+    let snapshot = Snapshot::new(&world, 0);
+    world.delete_now(first);
+    world.delete_now(owner);
+    // takes the id of first
+    world.create_now().with(PhysicState::new([5., 5.])).build();
+    snapshot.restore(&mut world);
+
+    assert_eq!(physics::checksum(&world), snapshot.checksum);
+
+    let owner = world.read::<Projectile>().get(projectile).unwrap().owner.unwrap();
+    assert!(world.is_alive(owner));
+    assert_eq!(owner.get_id(), 1);
+    assert_eq!(world.read::<PhysicState>().get(owner).unwrap().pos, [1., 0.]);
+
+    let collision_events = world.read_resource::<CollisionEvents>();
+    assert_eq!(collision_events.events.len(), 1);
+    assert_eq!((collision_events.events[0].a, collision_events.events[0].b), (owner, projectile));
+}
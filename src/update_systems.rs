@@ -1,6 +1,24 @@
 use utils::UpdateContext;
 use physics::update_systems::*;
+use monsters::update_systems::*;
+use director::update_systems::*;
+use weapons::update_systems::*;
+use health::update_systems::*;
+use projectiles::update_systems::*;
+use explosions::update_systems::*;
+use doors::update_systems::*;
 
 pub fn add_systems(planner: &mut ::specs::Planner<UpdateContext>) {
+    planner.add_system(ZombieSystem, "zombie", 20);
+    planner.add_system(SpiderSystem, "spider", 20);
+    planner.add_system(WeaponSystem, "weapon", 15);
+    planner.add_system(HitscanSystem, "hitscan", 14);
     planner.add_system(PhysicSystem, "physic", 10);
+    planner.add_system(PickupSystem, "pickup", 9);
+    planner.add_system(ProjectileSystem, "projectile", 8);
+    planner.add_system(ExplosionSystem, "explosion", 7);
+    planner.add_system(DoorSystem, "door", 6);
+    // after the systems pushing damages, before they are applied
+    planner.add_system(DirectorSystem, "director", 5);
+    planner.add_system(DamageSystem, "damage", 4);
 }
@@ -50,6 +50,44 @@ pub fn minus_pi_pi(a: f32) -> f32 {
     }
 }
 
+/// Xorshift random number generator:
+/// the same seed gives the same sequence on every platform
+#[derive(Clone)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        let state = seed ^ 0x9e3779b9;
+        let mut rng = Rng {
+            state: if state == 0 { 1 } else { state },
+        };
+        // close seeds must not give close sequences
+        for _ in 0..8 {
+            rng.next_u32();
+        }
+        rng
+    }
+    #[inline]
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+    /// Integer in [0, n[
+    #[inline]
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n
+    }
+    /// Float in [0, 1[
+    #[inline]
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
 pub mod math {
     use ::rusttype::Vector;
 
@@ -109,4 +147,5 @@ macro_rules! infer_type {
     ($t1:tt $t2:tt $t3:tt $t4:tt $t5:tt $t6:tt $t7:tt) => {::hlua::function7};
     ($t1:tt $t2:tt $t3:tt $t4:tt $t5:tt $t6:tt $t7:tt $t8:tt) => {::hlua::function8};
     ($t1:tt $t2:tt $t3:tt $t4:tt $t5:tt $t6:tt $t7:tt $t8:tt $t9:tt) => {::hlua::function9};
+    ($t1:tt $t2:tt $t3:tt $t4:tt $t5:tt $t6:tt $t7:tt $t8:tt $t9:tt $t10:tt) => {::hlua::function10};
 }
@@ -1,12 +0,0 @@
-use graphics::{self, Layer, obj};
-use specs;
-use specs::Join;
-use components::*;
-use utils::math::*;
-use utils::math;
-use colors;
-use itertools::Itertools;
-use super::*;
-
-pub fn draw_weapon(world: &mut specs::World, frame: &mut graphics::Frame) {
-}
@@ -0,0 +1,47 @@
+use super::*;
+
+impl_component! {
+    Aim: HashMapStorage,
+    Shoot: NullStorage,
+    Weapon: HashMapStorage,
+    NextWeapon: HashMapStorage,
+    Pickup: HashMapStorage,
+}
+
+#[derive(Clone)]
+pub struct Aim(pub f32);
+
+#[derive(Clone,Copy,Default)]
+pub struct Shoot;
+
+#[derive(Clone)]
+pub struct Weapon {
+    pub reload_factor: f32,
+    pub setup_factor: f32,
+    pub setdown_factor: f32,
+    pub state: State,
+    pub kind: Kind,
+    /// number of shots fired, seeds the spread
+    pub shots: u32,
+}
+
+impl Weapon {
+    /// reload, setup and setdown are durations in seconds
+    pub fn new(kind: Kind, reload: f32, setup: f32, setdown: f32) -> Self {
+        Weapon {
+            kind: kind,
+            state: State::Setup(0.),
+            reload_factor: 1./reload,
+            setup_factor: 1./setup,
+            setdown_factor: 1./setdown,
+            shots: 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct NextWeapon(pub Weapon);
+
+/// Weapon given to the first hero touching it
+#[derive(Clone)]
+pub struct Pickup(pub Weapon);
@@ -6,8 +6,22 @@ use utils::math::*;
 use utils::math;
 use colors;
 use itertools::Itertools;
+use resources::Tracers;
+use super::resources::TRACER_DURATION;
 use super::*;
 
+const TRACER_WIDTH: f32 = 0.05;
+
+/// Tracers fade out
+pub fn draw_tracers(world: &mut specs::World, frame: &mut graphics::Frame) {
+    let tracers = world.read_resource::<Tracers>();
+    for tracer in &tracers.0 {
+        let mut color = colors::BLACK;
+        color[3] = tracer.time / TRACER_DURATION;
+        frame.draw_line(tracer.from, tracer.to, TRACER_WIDTH, Layer::Middle, color);
+    }
+}
+
 pub fn draw_weapon(world: &mut specs::World, frame: &mut graphics::Frame) {
     let states = world.read::<PhysicState>();
     let weapons = world.read::<Weapon>();
@@ -16,7 +30,9 @@ pub fn draw_weapon(world: &mut specs::World, frame: &mut graphics::Frame) {
     for (weapons, state, aim) in (&weapons, &states, &aims).iter() {
         match weapons.kind {
             Kind::Sniper => draw_sniper(state.pos, aim.0, weapons.state.clone(), frame),
-            _ => unimplemented!(),
+            Kind::Shotgun => draw_gun(state.pos, aim.0, weapons.state.clone(), 1.6, 0.3, 0.2, frame),
+            Kind::Uzis => draw_gun(state.pos, aim.0, weapons.state.clone(), 1.2, 0.1, 0.15, frame),
+            Kind::Hammer(side) => draw_hammer(state.pos, aim.0, weapons.state.clone(), side, frame),
         }
     }
 }
@@ -86,6 +102,70 @@ fn interpolation(time: f32, points: &[Point]) -> (f32, f32, f32) {
     unreachable!();
 }
 
+/// Length of the weapon out of the body for each state
+fn weapon_length(state: State, len: f32, recoil: f32, time_recoil: f32) -> f32 {
+    match state {
+        State::Setup(t) => len*t,
+        State::Ready => len,
+        State::Reload(t) => {
+            interpolation(t, &[
+                          Point { time:             0., data: (       len, 0., 0.) },
+                          Point { time: time_recoil/2., data: (len-recoil, 0., 0.) },
+                          Point { time:    time_recoil, data: (       len, 0., 0.) },
+            ]).0
+        },
+        State::Setdown(t) => len*(1. - t),
+    }
+}
+
+/// Weapons without model are drawn as a barrel held by both hands
+fn draw_gun(pos: [f32; 2], aim: f32, state: State, len: f32, recoil: f32, width: f32, frame: &mut graphics::Frame) {
+    let left_hand = [-0.3, 0.1];
+    let right_hand = [-0.6, -0.1];
+    let left_shoulder = [0., 0.5];
+    let right_shoulder = [0., -0.5];
+
+    let delta_len = weapon_length(state, len, recoil, 0.4);
+
+    draw_arm(left_shoulder, left_hand, true, pos, aim, delta_len, 0., 0., frame);
+    draw_arm(right_shoulder, right_hand, false, pos, aim, delta_len, 0., 0., frame);
+
+    let tip = add(pos, mul(delta_len, [aim.cos(), aim.sin()]));
+    let back = sub(tip, mul(len/2., [aim.cos(), aim.sin()]));
+    frame.draw_line(back, tip, width, Layer::Middle, colors::BLACK);
+}
+
+/// The hammer swings from one side to the other at each hit
+fn draw_hammer(pos: [f32; 2], aim: f32, state: State, side: bool, frame: &mut graphics::Frame) {
+    let hand = [-0.2, 0.];
+    let left_shoulder = [0., 0.5];
+    let right_shoulder = [0., -0.5];
+
+    let len = 1.4;
+    let swing = 0.8 * if side { 1. } else { -1. };
+
+    let (delta_len, delta_aim) = match state {
+        State::Setup(t) => (len*t, swing),
+        State::Ready => (len, swing),
+        State::Reload(t) => {
+            let (l, a, _) = interpolation(t, &[
+                                          Point { time: 0., data: (len, -swing, 0.) },
+                                          Point { time: 0.3, data: (len, swing, 0.) },
+            ]);
+            (l, a)
+        },
+        State::Setdown(t) => (len*(1. - t), swing),
+    };
+
+    draw_arm(left_shoulder, hand, true, pos, aim, delta_len, delta_aim, 0., frame);
+    draw_arm(right_shoulder, hand, false, pos, aim, delta_len, delta_aim, 0., frame);
+
+    let direction = [(aim + delta_aim).cos(), (aim + delta_aim).sin()];
+    let head = add(pos, mul(delta_len, direction));
+    frame.draw_line(pos, head, 0.1, Layer::Middle, colors::BLACK);
+    frame.draw_circle(head[0], head[1], 0.3, Layer::Middle, colors::BLACK);
+}
+
 fn draw_sniper(pos: [f32; 2], aim: f32, state: State, frame: &mut graphics::Frame) {
     let left_hand = [-0.5, 0.1];
     let right_hand = [-1., -0.2];
@@ -95,7 +175,6 @@ fn draw_sniper(pos: [f32; 2], aim: f32, state: State, frame: &mut graphics::Fram
     let len = 2.;
     let recoil = 0.4;
     let time_recoil = 0.4;
-    let time_reload = 0.6;
 
     let (delta_len, delta_aim, delta_angle) = match state {
         State::Setup(t) => (len*t, 0., 0.),
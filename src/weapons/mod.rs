@@ -1,4 +1,5 @@
 pub mod components;
+pub mod resources;
 pub mod update_systems;
 pub mod draw_systems;
 
@@ -29,4 +30,12 @@ impl Kind {
             _ => None,
         }
     }
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Sniper => "sniper",
+            Kind::Shotgun => "shotgun",
+            Kind::Hammer(_) => "hammer",
+            Kind::Uzis => "uzis",
+        }
+    }
 }
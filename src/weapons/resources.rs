@@ -0,0 +1,92 @@
+use specs;
+use super::*;
+
+impl_resource! {
+    FireEvents,
+    Hitscans,
+    Tracers,
+}
+
+/// A weapon has fired
+#[derive(Clone)]
+pub struct FireEvent {
+    pub shooter: specs::Entity,
+    pub pos: [f32; 2],
+    pub angle: f32,
+    pub kind: Kind,
+    /// seed of the spread of the rays
+    pub seed: u32,
+}
+
+/// Weapons fired during the last update
+pub struct FireEvents(pub Vec<FireEvent>);
+impl FireEvents {
+    pub fn new() -> Self {
+        FireEvents(Vec::new())
+    }
+}
+
+/// Rays cast when a weapon fires
+#[derive(Clone)]
+pub struct Hitscan {
+    /// damage of each ray
+    pub damage: f32,
+    pub range: f32,
+    pub rays: u32,
+    /// maximal angle between a ray and the aim
+    pub spread: f32,
+    /// number of bodies with health a ray goes through
+    pub penetration: u32,
+}
+
+/// Hitscan of each kind of weapon
+pub struct Hitscans {
+    pub sniper: Hitscan,
+    pub shotgun: Hitscan,
+    pub hammer: Hitscan,
+    pub uzis: Hitscan,
+}
+impl Hitscans {
+    pub fn new() -> Self {
+        Hitscans {
+            sniper: Hitscan { damage: 80., range: 60., rays: 1, spread: 0., penetration: 2 },
+            shotgun: Hitscan { damage: 10., range: 12., rays: 8, spread: 0.25, penetration: 0 },
+            hammer: Hitscan { damage: 30., range: 2., rays: 1, spread: 0., penetration: 0 },
+            uzis: Hitscan { damage: 8., range: 20., rays: 1, spread: 0.08, penetration: 0 },
+        }
+    }
+    pub fn get(&self, kind: &Kind) -> &Hitscan {
+        match *kind {
+            Kind::Sniper => &self.sniper,
+            Kind::Shotgun => &self.shotgun,
+            Kind::Hammer(_) => &self.hammer,
+            Kind::Uzis => &self.uzis,
+        }
+    }
+    pub fn get_mut(&mut self, kind: &Kind) -> &mut Hitscan {
+        match *kind {
+            Kind::Sniper => &mut self.sniper,
+            Kind::Shotgun => &mut self.shotgun,
+            Kind::Hammer(_) => &mut self.hammer,
+            Kind::Uzis => &mut self.uzis,
+        }
+    }
+}
+
+/// Duration of tracers in seconds
+pub const TRACER_DURATION: f32 = 0.15;
+
+/// Visible trace of a ray
+pub struct Tracer {
+    pub from: [f32; 2],
+    pub to: [f32; 2],
+    /// remaining time to be drawn
+    pub time: f32,
+}
+
+pub struct Tracers(pub Vec<Tracer>);
+impl Tracers {
+    pub fn new() -> Self {
+        Tracers(Vec::new())
+    }
+}
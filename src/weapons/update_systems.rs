@@ -0,0 +1,209 @@
+use specs;
+use components::*;
+use resources::{CollisionEvents, Damage, Damages, FireEvents, Hitscans, LuaCallbacks, PhysicWorld, Tracers};
+use health::DamageKind;
+use specs::Join;
+use physics::{RayCast, ContinueOrStop, ContactState};
+use entities::HITSCAN_MASK;
+use utils::Rng;
+use super::*;
+use super::resources::{FireEvent, Tracer, TRACER_DURATION};
+
+/// Hammer changes of side at each hit
+fn fire(weapon: &mut Weapon, entity: specs::Entity, pos: [f32; 2], angle: f32, events: &mut FireEvents, callbacks: &mut LuaCallbacks) {
+    if let Kind::Hammer(ref mut b) = weapon.kind {
+        *b = !*b;
+    }
+    events.0.push(FireEvent {
+        shooter: entity,
+        pos: pos,
+        angle: angle,
+        kind: weapon.kind.clone(),
+        seed: weapon.shots ^ entity.get_id().wrapping_mul(0x9e3779b9),
+    });
+    weapon.shots = weapon.shots.wrapping_add(1);
+    callbacks.0.push(format!("weapon_fired({},\"{}\")", entity.get_id(), weapon.kind.name()));
+}
+
+pub struct WeaponSystem;
+impl specs::System<::utils::UpdateContext> for WeaponSystem {
+    fn run(&mut self, arg: specs::RunArg, context: ::utils::UpdateContext) {
+        let (mut weapons, mut next_weapons, shoots, aims, states, mut events, mut callbacks, entities) = arg.fetch(|world| {
+            (
+                world.write::<Weapon>(),
+                world.write::<NextWeapon>(),
+                world.read::<Shoot>(),
+                world.read::<Aim>(),
+                world.read::<PhysicState>(),
+                world.write_resource::<FireEvents>(),
+                world.write_resource::<LuaCallbacks>(),
+                world.entities(),
+            )
+        });
+
+        events.0.clear();
+
+        // create a fake weapon if next weapon and none weapon
+        for (_, entity) in (&mut next_weapons, &entities).iter() {
+            if weapons.get(entity).is_none() {
+                let fake_weapon =  Weapon {
+                    reload_factor: 0.,
+                    setup_factor: 0.,
+                    setdown_factor: 0.,
+                    state: State::Setdown(1.),
+                    kind: Kind::Sniper,
+                    shots: 0,
+                };
+                match weapons.insert(entity, fake_weapon) {
+                    specs::InsertResult::Inserted => (),
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        for (weapon, physic_state, entity) in (&mut weapons, &states, &entities).iter() {
+            let shoot = shoots.get(entity).is_some();
+            let aim = aims.get(entity).map_or(0., |aim| aim.0);
+
+            // set down
+            match weapon.state {
+                State::Setdown(_) => (),
+                ref mut state @ _ => if next_weapons.get(entity).is_some() {
+                    *state = State::Setdown(0.);
+                },
+            }
+
+            // update loading
+            match weapon.state {
+                State::Reload(ref mut t) => *t += context.dt*weapon.reload_factor,
+                State::Setup(ref mut t) => *t += context.dt*weapon.setup_factor,
+                State::Setdown(ref mut t) => *t += context.dt*weapon.setdown_factor,
+                State::Ready => (),
+            }
+
+            // update state and weapon if set down
+            match weapon.state {
+                State::Reload(t) | State::Setup(t) => if t >= 1. {
+                    if shoot {
+                        weapon.state = State::Reload(t-1.);
+                        fire(weapon, entity, physic_state.pos, aim, &mut events, &mut callbacks);
+                    } else {
+                        weapon.state = State::Ready;
+                    };
+                },
+                State::Ready => if shoot {
+                    weapon.state = State::Reload(context.dt*weapon.reload_factor);
+                    fire(weapon, entity, physic_state.pos, aim, &mut events, &mut callbacks);
+                },
+                State::Setdown(t) => if t >= 1. {
+                    *weapon = next_weapons.remove(entity).unwrap().0;
+                    weapon.state = State::Setup(t-1.);
+                },
+            };
+        }
+    }
+}
+
+/// Cast the rays of weapons fired during this update, excluding the shooter:
+/// a ray damages each body with health it goes through and stops on a body
+/// without health or when the penetration of the weapon is exceeded.
+/// The hammer does melee damages, other weapons bullet damages
+pub struct HitscanSystem;
+impl specs::System<::utils::UpdateContext> for HitscanSystem {
+    fn run(&mut self, arg: specs::RunArg, context: ::utils::UpdateContext) {
+        let (healths, events, hitscans, mut damages, mut tracers, physic_world) = arg.fetch(|world| {
+            (
+                world.read::<Health>(),
+                world.read_resource::<FireEvents>(),
+                world.read_resource::<Hitscans>(),
+                world.write_resource::<Damages>(),
+                world.write_resource::<Tracers>(),
+                world.read_resource::<PhysicWorld>(),
+            )
+        });
+
+        for tracer in &mut tracers.0 {
+            tracer.time -= context.dt;
+        }
+        tracers.0.retain(|tracer| tracer.time > 0.);
+
+        for event in &events.0 {
+            let hitscan = hitscans.get(&event.kind);
+            let kind = match event.kind {
+                Kind::Hammer(_) => DamageKind::Melee,
+                _ => DamageKind::Bullet,
+            };
+            let mut rng = Rng::new(event.seed);
+            for _ in 0..hitscan.rays {
+                let angle = event.angle + (2. * rng.next_f32() - 1.) * hitscan.spread;
+                let ray = RayCast {
+                    origin: event.pos,
+                    angle: angle,
+                    length: hitscan.range,
+                    mask: HITSCAN_MASK,
+                    group: !0,
+                    not: vec!(event.shooter),
+                };
+                let mut length = hitscan.range;
+                let mut penetrated = 0;
+                physic_world.raycast(&ray, &mut |(info, min, _)| {
+                    if healths.get(info.entity).is_some() {
+                        damages.0.push(Damage {
+                            target: info.entity,
+                            source: Some(event.shooter),
+                            amount: hitscan.damage,
+                            kind: kind,
+                        });
+                        if penetrated < hitscan.penetration {
+                            penetrated += 1;
+                            return ContinueOrStop::Continue;
+                        }
+                    }
+                    length = min.max(0.);
+                    ContinueOrStop::Stop
+                });
+                tracers.0.push(Tracer {
+                    from: event.pos,
+                    to: [event.pos[0] + length * angle.cos(), event.pos[1] + length * angle.sin()],
+                    time: TRACER_DURATION,
+                });
+            }
+        }
+    }
+}
+
+/// Give the weapon of a pickup to the hero touching it and remove the pickup
+pub struct PickupSystem;
+impl specs::System<::utils::UpdateContext> for PickupSystem {
+    fn run(&mut self, arg: specs::RunArg, _context: ::utils::UpdateContext) {
+        let (pickups, player_controls, mut next_weapons, collision_events, mut physic_world, mut callbacks) = arg.fetch(|world| {
+            (
+                world.read::<Pickup>(),
+                world.read::<PlayerControl>(),
+                world.write::<NextWeapon>(),
+                world.read_resource::<CollisionEvents>(),
+                world.write_resource::<PhysicWorld>(),
+                world.write_resource::<LuaCallbacks>(),
+            )
+        });
+
+        let mut taken = vec!();
+        for event in &collision_events.events {
+            if event.state != ContactState::Started {
+                continue;
+            }
+            for &(pickup, hero) in &[(event.a, event.b), (event.b, event.a)] {
+                if taken.contains(&pickup) || player_controls.get(hero).is_none() {
+                    continue;
+                }
+                if let Some(pickup_weapon) = pickups.get(pickup) {
+                    next_weapons.insert(hero, NextWeapon(pickup_weapon.0.clone()));
+                    physic_world.remove_static(pickup);
+                    arg.delete(pickup);
+                    callbacks.0.push(format!("pickup_taken({},{},\"{}\")", pickup.get_id(), hero.get_id(), pickup_weapon.0.kind.name()));
+                    taken.push(pickup);
+                }
+            }
+        }
+    }
+}